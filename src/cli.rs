@@ -1,5 +1,6 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand};
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(
@@ -33,14 +34,77 @@ pub struct Cli {
     /// Force operation
     #[arg(short = 'f', long, global = true)]
     pub force: bool,
+
+    /// Show each linked worktree as its own row
+    #[arg(short = 'w', long, global = true)]
+    pub worktrees: bool,
+
+    /// Close the TUI as soon as every repo has finished
+    #[arg(long, global = true)]
+    pub exit_when_done: bool,
+
+    /// Run the command again every INTERVAL (e.g. 30s, 5m) and highlight
+    /// repos whose result changed
+    #[arg(
+        long,
+        global = true,
+        value_name = "INTERVAL",
+        value_parser = parse_interval,
+        conflicts_with = "exit_when_done"
+    )]
+    pub watch: Option<Duration>,
+
+    /// Also list repos with changes in the report printed on exit
+    #[arg(long, global = true)]
+    pub report_changed: bool,
+}
+
+/// Longest `--watch` interval: a day
+const MAX_INTERVAL_SECS: u64 = 24 * 3600;
+
+/// Interval like `30s`, `5m`, `1h` or plain seconds
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(pos) => value.split_at(pos),
+        None => (value, "s"),
+    };
+    let n: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid interval: {}", value))?;
+    let secs = match unit {
+        "s" => Some(n),
+        "m" => n.checked_mul(60),
+        "h" => n.checked_mul(3600),
+        _ => return Err(format!("invalid interval unit: {}", unit)),
+    };
+    match secs {
+        Some(0) => Err("interval must be at least 1s".into()),
+        Some(secs) if secs <= MAX_INTERVAL_SECS => Ok(Duration::from_secs(secs)),
+        _ => Err("interval must be at most 24h".into()),
+    }
+}
+
+/// A command typed at the TUI's `:` prompt
+#[derive(Parser)]
+#[command(name = ":", no_binary_name = true, disable_help_subcommand = true)]
+pub struct Prompt {
+    #[command(subcommand)]
+    pub command: Command,
 }
 
 #[derive(Subcommand, Clone)]
 pub enum Command {
     /// Pull latest changes (clone if missing)
-    Update,
+    Update {
+        #[command(flatten)]
+        fetch: FetchOpts,
+    },
     /// Alias for update
-    Pull,
+    Pull {
+        #[command(flatten)]
+        fetch: FetchOpts,
+    },
     /// Show working tree status
     Status,
     /// Show diffs
@@ -48,45 +112,323 @@ pub enum Command {
     /// Push commits
     Push,
     /// Fetch from remotes
-    Fetch,
+    Fetch {
+        #[command(flatten)]
+        fetch: FetchOpts,
+    },
     /// Clone repos (skip if exists)
-    Checkout,
+    Checkout {
+        #[command(flatten)]
+        clone: CloneOpts,
+    },
     /// Alias for checkout
-    Co,
+    Co {
+        #[command(flatten)]
+        clone: CloneOpts,
+    },
+    /// Fetch full history for shallow clones
+    Unshallow,
+    /// List worktrees of each repo with branch and dirty state
+    Worktrees,
+    /// Manage worktrees across repos
+    Worktree {
+        #[command(subcommand)]
+        action: WorktreeAction,
+    },
+    /// Manage the local mirror cache
+    Mirror {
+        #[command(subcommand)]
+        action: MirrorAction,
+    },
     /// Run an arbitrary command in each repo
     Run {
         /// Command to run
         #[arg(trailing_var_arg = true, required = true)]
         cmd: Vec<String>,
     },
-    /// Register current repo in config
-    Register,
+    /// Register repos in config (default: current dir)
+    Register {
+        /// Repo directories to register
+        paths: Vec<PathBuf>,
+        /// Remote to take the clone URL from
+        #[arg(long, default_value = "origin")]
+        remote: String,
+    },
+    /// Find unregistered git repos under a directory and register them
+    Discover {
+        /// Directory to search (default: base dir)
+        dir: Option<PathBuf>,
+        /// Remote to take the clone URL from
+        #[arg(long, default_value = "origin")]
+        remote: String,
+        /// Register without asking for confirmation
+        #[arg(short = 'y', long)]
+        yes: bool,
+    },
+    /// Remove a repo from config (default: current dir)
+    Unregister {
+        /// Repo directory to remove
+        path: Option<PathBuf>,
+    },
+    /// Edit config entries
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Check config against the filesystem (non-zero exit on problems)
+    Doctor,
+    /// Show the results of the previous run again without re-running it
+    Last,
+    /// List recorded runs
+    History {
+        #[command(subcommand)]
+        action: Option<HistoryAction>,
+    },
     /// List configured repos
-    List,
+    List {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
     /// Alias for list
-    Ls,
+    Ls {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Options for commands that fetch from remotes
+#[derive(Args, Clone, Default)]
+pub struct FetchOpts {
+    /// Fetch from the local mirror first, then from the remote
+    #[arg(long)]
+    pub from_mirror: bool,
+    /// Also fetch and update submodules
+    #[arg(long)]
+    pub recurse_submodules: bool,
+}
+
+/// Clone options for checkout, overriding per-section settings
+#[derive(Args, Clone, Default)]
+pub struct CloneOpts {
+    /// Shallow clone (same as --depth 1)
+    #[arg(long)]
+    pub shallow: bool,
+    /// Truncate history to N commits
+    #[arg(long, value_name = "N")]
+    pub depth: Option<u32>,
+    /// Partial clone filter, e.g. blob:none
+    #[arg(long, value_name = "SPEC")]
+    pub filter: Option<String>,
+    /// Only fetch the branch being checked out
+    #[arg(long)]
+    pub single_branch: bool,
+    /// Clone submodules too
+    #[arg(long)]
+    pub recurse_submodules: bool,
+    /// Borrow objects from mirrors in DIR (mirror cache layout) when present
+    #[arg(long, value_name = "DIR")]
+    pub reference_dir: Option<PathBuf>,
+    /// Copy borrowed objects so clones don't depend on the mirror
+    #[arg(long)]
+    pub dissociate: bool,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum WorktreeAction {
+    /// Create a worktree for BRANCH in each repo (default: all repos)
+    Add {
+        /// Branch to check out, created if it doesn't exist
+        branch: String,
+        /// Repos to add the worktree to (names or section paths)
+        repos: Vec<String>,
+        /// Root of the worktree layout (default: <base dir>/.worktrees)
+        #[arg(long, value_name = "DIR")]
+        root: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum MirrorAction {
+    /// Create or fetch a bare mirror of every repo
+    Update,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum HistoryAction {
+    /// Show repos whose status changed between two runs
+    Diff {
+        /// Older run, numbered as in `mrx history` (default: the previous
+        /// run of the same command)
+        old: Option<usize>,
+        /// Newer run (default: 1, the latest)
+        new: Option<usize>,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum ConfigAction {
+    /// Set a key in a repo's section
+    Set {
+        /// Repo name or section path
+        repo: String,
+        key: String,
+        value: String,
+    },
 }
 
 impl Command {
     pub fn display_name(&self) -> &'static str {
         match self {
-            Command::Update | Command::Pull => "update",
+            Command::Update { .. } | Command::Pull { .. } => "update",
             Command::Status => "status",
             Command::Diff => "diff",
             Command::Push => "push",
-            Command::Fetch => "fetch",
-            Command::Checkout | Command::Co => "checkout",
+            Command::Fetch { .. } => "fetch",
+            Command::Checkout { .. } | Command::Co { .. } => "checkout",
+            Command::Unshallow => "unshallow",
+            Command::Mirror { .. } => "mirror",
+            Command::Worktrees => "worktrees",
+            Command::Worktree { .. } => "worktree add",
             Command::Run { .. } => "run",
-            Command::Register => "register",
-            Command::List | Command::Ls => "list",
+            Command::Register { .. } => "register",
+            Command::Discover { .. } => "discover",
+            Command::Unregister { .. } => "unregister",
+            Command::Config { .. } => "config",
+            Command::Doctor => "doctor",
+            Command::Last => "last",
+            Command::History { .. } => "history",
+            Command::List { .. } | Command::Ls { .. } => "list",
         }
     }
 
-    pub fn is_list(&self) -> bool {
-        matches!(self, Command::List | Command::Ls)
+    pub fn is_doctor(&self) -> bool {
+        matches!(self, Command::Doctor)
+    }
+
+    /// Commands that run an operation in each repo and show it in the TUI
+    pub fn is_operation(&self) -> bool {
+        matches!(
+            self,
+            Command::Update { .. }
+                | Command::Pull { .. }
+                | Command::Status
+                | Command::Diff
+                | Command::Push
+                | Command::Fetch { .. }
+                | Command::Checkout { .. }
+                | Command::Co { .. }
+                | Command::Unshallow
+                | Command::Worktree { .. }
+                | Command::Mirror { .. }
+                | Command::Run { .. }
+        )
+    }
+
+    /// Commands that only inspect repos, so running them again is harmless
+    pub fn is_read_only(&self) -> bool {
+        matches!(self, Command::Status | Command::Diff)
+    }
+
+    /// Commands that edit the config file instead of running operations
+    pub fn is_config_edit(&self) -> bool {
+        matches!(
+            self,
+            Command::Register { .. }
+                | Command::Discover { .. }
+                | Command::Unregister { .. }
+                | Command::Config { .. }
+        )
+    }
+}
+
+/// The subcommand and its own arguments from a command line (without the
+/// program name), dropping global options wherever they appear
+pub fn command_args(args: &[String]) -> Vec<String> {
+    let cli = Cli::command();
+    let globals: Vec<&clap::Arg> = cli.get_arguments().filter(|a| a.is_global_set()).collect();
+    let takes_value = |a: &clap::Arg| a.get_action().takes_values();
+
+    let mut out: Vec<String> = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        // Everything from `--` or the first word of `run`'s command is kept
+        if arg == "--" || (out.len() == 1 && out[0] == "run" && !arg.starts_with('-')) {
+            out.push(arg.clone());
+            out.extend(iter.cloned());
+            break;
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, _)) => (name, true),
+                None => (long, false),
+            };
+            if let Some(global) = globals.iter().find(|a| a.get_long() == Some(name)) {
+                if takes_value(global) && !value {
+                    iter.next();
+                }
+                continue;
+            }
+        } else if let Some(shorts) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+            // A cluster like `-vj4` is global only if every flag in it is
+            let mut chars = shorts.char_indices();
+            let mut skip_next = false;
+            let is_global = loop {
+                let Some((i, c)) = chars.next() else {
+                    break true;
+                };
+                let Some(global) = globals.iter().find(|a| a.get_short() == Some(c)) else {
+                    break false;
+                };
+                if takes_value(global) {
+                    skip_next = i + c.len_utf8() == shorts.len();
+                    break true;
+                }
+            };
+            if is_global {
+                if skip_next {
+                    iter.next();
+                }
+                continue;
+            }
+        }
+        out.push(arg.clone());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_args_drops_global_options() {
+        let args = |line: &str| -> Vec<String> {
+            command_args(&line.split(' ').map(String::from).collect::<Vec<_>>())
+        };
+        assert_eq!(args("status"), vec!["status"]);
+        assert_eq!(args("-j 4 status --exit-when-done"), vec!["status"]);
+        assert_eq!(args("--jobs=4 -vj4 status -c x"), vec!["status"]);
+        assert_eq!(
+            args("fetch --from-mirror --watch 5m"),
+            vec!["fetch", "--from-mirror"]
+        );
+        assert_eq!(args("-j 2 run make -j 4"), vec!["run", "make", "-j", "4"]);
     }
 
-    pub fn is_register(&self) -> bool {
-        matches!(self, Command::Register)
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_interval("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_interval("1h"), Ok(Duration::from_secs(3600)));
+        assert_eq!(parse_interval("45"), Ok(Duration::from_secs(45)));
+        assert!(parse_interval("0s").is_err());
+        assert!(parse_interval("10d").is_err());
+        assert!(parse_interval("s").is_err());
+        assert_eq!(parse_interval("24h"), Ok(Duration::from_secs(86400)));
+        assert!(parse_interval("25h").is_err());
+        assert!(parse_interval("18446744073709551615").is_err());
+        assert!(parse_interval("18446744073709551615h").is_err());
     }
 }
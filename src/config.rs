@@ -1,82 +1,879 @@
+use crate::shellwords;
+use crate::vcs::Vcs;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct Repo {
     pub name: String,
+    /// Section name: the repo's path relative to the base dir
+    pub section: String,
     pub path: PathBuf,
-    pub clone_url: Option<String>,
+    pub clone: Option<CloneSpec>,
+    /// Clone and pull submodules recursively
+    pub submodules: bool,
+    pub vcs: Vcs,
+    /// Sections that must finish successfully before this repo runs
+    pub depends: Vec<String>,
+    /// Group from the section's `group =` key, for grouping in the TUI
+    pub group: Option<String>,
 }
 
-pub fn parse_config(config_path: &Path, base_dir: &Path) -> Vec<Repo> {
+impl Repo {
+    pub fn clone_url(&self) -> Option<&str> {
+        self.clone.as_ref().map(|c| c.url.as_str())
+    }
+}
+
+/// The `git clone` invocation from a section's `checkout` command
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CloneSpec {
+    pub url: String,
+    /// Destination directory as written in the checkout command. The
+    /// section path decides where the repo actually goes.
+    pub dest: Option<String>,
+    pub branch: Option<String>,
+    pub depth: Option<u32>,
+    /// Partial clone filter, e.g. `blob:none`
+    pub filter: Option<String>,
+    pub single_branch: bool,
+    pub recurse_submodules: bool,
+    /// Local repo to borrow objects from
+    pub reference: Option<PathBuf>,
+    /// Copy borrowed objects so the clone doesn't depend on `reference`
+    pub dissociate: bool,
+    /// Any other clone options, passed through verbatim
+    pub extra_args: Vec<String>,
+}
+
+impl CloneSpec {
+    /// Arguments for `git clone` into `dest`
+    pub fn args(&self, dest: &Path) -> Vec<String> {
+        let mut args = vec!["clone".to_string()];
+        args.extend(self.extra_args.iter().cloned());
+        if let Some(branch) = &self.branch {
+            args.push("--branch".into());
+            args.push(branch.clone());
+        }
+        if let Some(depth) = self.depth {
+            args.push(format!("--depth={}", depth));
+        }
+        if let Some(filter) = &self.filter {
+            args.push(format!("--filter={}", filter));
+        }
+        if self.single_branch {
+            args.push("--single-branch".into());
+        }
+        if self.recurse_submodules {
+            args.push("--recurse-submodules".into());
+        }
+        if let Some(reference) = &self.reference {
+            args.push(format!("--reference={}", reference.display()));
+            if self.dissociate {
+                args.push("--dissociate".into());
+            }
+        }
+        args.push("--".into());
+        args.push(self.url.clone());
+        args.push(dest.to_string_lossy().to_string());
+        args
+    }
+}
+
+/// Read per-section clone settings (`depth`, `filter`, `single_branch`,
+/// `reference`, `dissociate`), which override the checkout command.
+fn apply_section_clone_opts(
+    spec: &mut CloneSpec,
+    ini: &configparser::ini::Ini,
+    section: &str,
+    base_dir: &Path,
+) -> Result<(), String> {
+    // A relative `--reference` in the checkout command means the same as
+    // in the `reference` key, not something relative to mrx's cwd
+    if let Some(reference) = &spec.reference {
+        spec.reference = Some(base_dir.join(reference));
+    }
+    if let Some(depth) = ini.get(section, "depth") {
+        spec.depth = Some(
+            depth
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid depth: {}", depth))?,
+        );
+    }
+    if let Some(filter) = ini.get(section, "filter") {
+        spec.filter = Some(filter.trim().to_string());
+    }
+    if let Some(v) = ini.get(section, "single_branch") {
+        spec.single_branch = parse_bool(&v)?;
+    }
+    if let Some(reference) = ini.get(section, "reference") {
+        spec.reference = Some(base_dir.join(reference.trim()));
+    }
+    if let Some(v) = ini.get(section, "dissociate") {
+        spec.dissociate = parse_bool(&v)?;
+    }
+    Ok(())
+}
+
+pub fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(format!("invalid boolean: {}", value)),
+    }
+}
+
+/// `git clone` options that take a separate value argument
+const CLONE_OPTS_WITH_VALUE: &[&str] = &[
+    "-b",
+    "--branch",
+    "--depth",
+    "-o",
+    "--origin",
+    "-c",
+    "--config",
+    "-j",
+    "--jobs",
+    "-u",
+    "--upload-pack",
+    "--reference",
+    "--reference-if-able",
+    "--separate-git-dir",
+    "--template",
+    "--filter",
+    "--shallow-since",
+    "--shallow-exclude",
+    "--server-option",
+    "--bundle-uri",
+    "--revision",
+];
+
+/// Section holding mrx's own settings rather than a repo
+pub const SETTINGS_SECTION: &str = "mrx";
+
+/// Settings from the `[mrx]` section
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    /// Max concurrent remote operations per host, unless overridden below
+    pub host_jobs: Option<usize>,
+    /// Per-host overrides from `host_jobs.<host> = N`
+    pub host_limits: BTreeMap<String, usize>,
+    /// Command the TUI's `o` key runs in the selected repo
+    pub open_command: Option<String>,
+    /// Whether to run a read-only command again for a repo after returning
+    /// from a shell, editor or `open_command` in it
+    pub refresh_after_open: Option<bool>,
+    /// TUI color theme: `default`, `high-contrast` or `mono`
+    pub theme: Option<String>,
+    /// TUI icons: `unicode` or `ascii`
+    pub icons: Option<String>,
+    /// Color overrides from `color.<role> = <color>`
+    pub colors: BTreeMap<String, String>,
+    /// Key bindings from `key.<action> = <key>`
+    pub keys: BTreeMap<String, String>,
+}
+
+impl Settings {
+    pub fn has_host_limits(&self) -> bool {
+        self.host_jobs.is_some() || !self.host_limits.is_empty()
+    }
+
+    /// Concurrency limit for remote operations against `host`
+    pub fn host_limit(&self, host: &str) -> Option<usize> {
+        self.host_limits
+            .get(&host.to_ascii_lowercase())
+            .copied()
+            .or(self.host_jobs)
+    }
+
+    pub fn open_command(&self) -> &str {
+        self.open_command.as_deref().unwrap_or("lazygit")
+    }
+
+    pub fn refresh_after_open(&self) -> bool {
+        self.refresh_after_open.unwrap_or(true)
+    }
+}
+
+fn read_ini(config_path: &Path) -> Option<configparser::ini::Ini> {
     let content = match std::fs::read_to_string(config_path) {
         Ok(c) => c,
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
         Err(e) => {
             eprintln!("error: cannot read {}: {}", config_path.display(), e);
             std::process::exit(1);
         }
     };
 
-    let mut ini = configparser::ini::Ini::new();
+    // Sections are paths, so they keep their case
+    let mut ini = configparser::ini::Ini::new_cs();
     if let Err(e) = ini.read(content) {
         eprintln!("error: cannot parse {}: {}", config_path.display(), e);
         std::process::exit(1);
     }
+    Some(ini)
+}
+
+pub fn parse_settings(config_path: &Path) -> Settings {
+    let mut settings = Settings::default();
+    let Some(ini) = read_ini(config_path) else {
+        return settings;
+    };
+    let Some(section) = ini.get_map_ref().get(SETTINGS_SECTION) else {
+        return settings;
+    };
+
+    for (key, value) in section {
+        let Some(value) = value else { continue };
+        let value = value.trim();
+        match key.as_str() {
+            "open_command" => settings.open_command = Some(value.to_string()),
+            "theme" => settings.theme = Some(value.to_string()),
+            "icons" => settings.icons = Some(value.to_string()),
+            _ if key.starts_with("color.") => {
+                settings
+                    .colors
+                    .insert(key["color.".len()..].to_string(), value.to_string());
+            }
+            _ if key.starts_with("key.") => {
+                settings
+                    .keys
+                    .insert(key["key.".len()..].to_string(), value.to_string());
+            }
+            "refresh_after_open" => match parse_bool(value) {
+                Ok(b) => settings.refresh_after_open = Some(b),
+                Err(e) => eprintln!("warning: [{}] {}: {}", SETTINGS_SECTION, key, e),
+            },
+            _ if key == "host_jobs" || key.starts_with("host_jobs.") => {
+                let limit = match value.parse::<usize>() {
+                    Ok(n) if n > 0 => n,
+                    _ => {
+                        eprintln!("warning: [{}] invalid {}: {}", SETTINGS_SECTION, key, value);
+                        continue;
+                    }
+                };
+                match key.strip_prefix("host_jobs.") {
+                    Some(host) => {
+                        settings
+                            .host_limits
+                            .insert(host.to_ascii_lowercase(), limit);
+                    }
+                    None => settings.host_jobs = Some(limit),
+                }
+            }
+            _ => eprintln!("warning: [{}] unknown setting: {}", SETTINGS_SECTION, key),
+        }
+    }
+    settings
+}
+
+/// Host name of a clone URL (`https://host/...`, `ssh://user@host:port/...`
+/// or scp-style `user@host:path`), `None` for local paths.
+pub fn url_host(url: &str) -> Option<String> {
+    let url = url.trim();
+    let authority = if let Some((scheme, rest)) = url.split_once("://") {
+        if scheme == "file" {
+            return None;
+        }
+        rest.split('/').next()?
+    } else {
+        // scp-style needs a colon before any slash
+        let (host, _) = url.split_once(':')?;
+        if host.contains('/') {
+            return None;
+        }
+        host
+    };
+    let host = authority.rsplit('@').next()?;
+    let host = match host.strip_prefix('[') {
+        Some(v6) => v6.split(']').next()?,
+        None => host.split(':').next()?,
+    };
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+pub fn parse_config(config_path: &Path, base_dir: &Path) -> Vec<Repo> {
+    let Some(ini) = read_ini(config_path) else {
+        return Vec::new();
+    };
+
+    let mut sections: Vec<String> = ini
+        .sections()
+        .into_iter()
+        .filter(|s| s != SETTINGS_SECTION)
+        .collect();
+    sections.sort();
+
+    let explicit: Vec<Option<String>> = sections
+        .iter()
+        .map(|s| ini.get(s, "name").filter(|n| !n.trim().is_empty()))
+        .collect();
+    let names = derive_names(&sections, &explicit);
 
     let mut repos: Vec<Repo> = Vec::new();
+    let mut raw_depends: Vec<Vec<String>> = Vec::new();
 
-    for section in ini.sections() {
-        let name = section.rsplit('/').next().unwrap_or(&section).to_string();
+    for (section, name) in sections.iter().zip(names) {
+        let abs_path = base_dir.join(section);
 
-        let abs_path = base_dir.join(&section);
+        let checkout = ini
+            .get(section, "checkout")
+            .and_then(|cmd| match parse_checkout(&cmd) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    eprintln!(
+                        "warning: [{}] cannot parse checkout command: {}",
+                        section, e
+                    );
+                    None
+                }
+            });
+        let checkout_vcs = checkout.as_ref().map(|(vcs, _)| *vcs);
+        let mut clone = checkout.map(|(_, spec)| spec);
 
-        let clone_url = ini
-            .get(&section, "checkout")
-            .and_then(|cmd| extract_clone_url(&cmd));
+        // Explicit `vcs =` wins, then what's on disk, then the checkout command
+        let explicit_vcs = ini.get(section, "vcs").and_then(|v| {
+            let vcs = Vcs::parse(&v);
+            if vcs.is_none() {
+                eprintln!("warning: [{}] unknown vcs: {}", section, v);
+            }
+            vcs
+        });
+        let vcs = explicit_vcs
+            .or_else(|| Vcs::detect(&abs_path))
+            .or(checkout_vcs)
+            .unwrap_or_default();
+        if let Some(spec) = &mut clone {
+            if let Err(e) = apply_section_clone_opts(spec, &ini, section, base_dir) {
+                eprintln!("warning: [{}] {}", section, e);
+            }
+        }
+
+        let submodules = match ini.get(section, "submodules").map(|v| parse_bool(&v)) {
+            Some(Ok(v)) => v,
+            Some(Err(e)) => {
+                eprintln!("warning: [{}] {}", section, e);
+                false
+            }
+            None => clone.as_ref().is_some_and(|c| c.recurse_submodules),
+        };
+        if let Some(spec) = &mut clone {
+            spec.recurse_submodules = submodules;
+        }
 
         repos.push(Repo {
             name,
+            section: section.clone(),
             path: abs_path,
-            clone_url,
+            clone,
+            submodules,
+            vcs,
+            depends: Vec::new(),
+            group: ini.get(section, "group").filter(|g| !g.trim().is_empty()),
         });
+        raw_depends.push(
+            ini.get(section, "depends")
+                .map(|v| v.split_whitespace().map(String::from).collect())
+                .unwrap_or_default(),
+        );
     }
 
-    repos.sort_by(|a, b| a.name.cmp(&b.name));
+    resolve_depends(&mut repos, raw_depends);
     repos
 }
 
-fn extract_clone_url(checkout_cmd: &str) -> Option<String> {
-    let tokens: Vec<&str> = checkout_cmd.split_whitespace().collect();
-    // find "clone" then take the next token as the URL
-    for (i, tok) in tokens.iter().enumerate() {
-        if *tok == "clone" {
-            if let Some(url) = tokens.get(i + 1) {
-                return Some(url.trim_matches('\'').trim_matches('"').to_string());
+/// Check that the repos' dependencies can be scheduled. Commands that
+/// only read or edit the config don't need this, so a cycle doesn't stop
+/// `doctor` or `config set` from being used to find and fix it.
+pub fn check_dependencies(repos: &[Repo]) -> Result<(), String> {
+    match find_cycle(repos) {
+        Some(cycle) => Err(format!("dependency cycle: {}", cycle.join(" -> "))),
+        None => Ok(()),
+    }
+}
+
+/// Turn `depends =` entries (repo names or section paths) into sections
+fn resolve_depends(repos: &mut [Repo], raw: Vec<Vec<String>>) {
+    let resolved: Vec<Vec<String>> = raw
+        .iter()
+        .zip(repos.iter())
+        .map(|(deps, repo)| {
+            deps.iter()
+                .filter_map(|dep| {
+                    let target = repos
+                        .iter()
+                        .find(|r| r.section == *dep)
+                        .or_else(|| repos.iter().find(|r| r.name == *dep));
+                    if target.is_none() {
+                        eprintln!("warning: [{}] unknown dependency: {}", repo.section, dep);
+                    }
+                    target.map(|r| r.section.clone())
+                })
+                .collect()
+        })
+        .collect();
+    for (repo, deps) in repos.iter_mut().zip(resolved) {
+        repo.depends = deps;
+    }
+}
+
+/// First dependency cycle found, as the names along it (first name repeated
+/// at the end), or `None` if the dependencies form a DAG.
+pub fn find_cycle(repos: &[Repo]) -> Option<Vec<String>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        New,
+        InProgress,
+        Done,
+    }
+
+    fn visit(i: usize, repos: &[Repo], marks: &mut [Mark], stack: &mut Vec<usize>) -> bool {
+        marks[i] = Mark::InProgress;
+        stack.push(i);
+        for dep in &repos[i].depends {
+            let Some(j) = repos.iter().position(|r| r.section == *dep) else {
+                continue;
+            };
+            let mark = marks[j];
+            match mark {
+                Mark::InProgress => {
+                    stack.push(j);
+                    return true;
+                }
+                Mark::New if visit(j, repos, marks, stack) => return true,
+                _ => {}
             }
         }
+        stack.pop();
+        marks[i] = Mark::Done;
+        false
+    }
+
+    let mut marks = vec![Mark::New; repos.len()];
+    for i in 0..repos.len() {
+        let mut stack = Vec::new();
+        if marks[i] == Mark::New && visit(i, repos, &mut marks, &mut stack) {
+            let last = *stack.last().unwrap();
+            let start = stack.iter().position(|&j| j == last).unwrap();
+            return Some(
+                stack[start..]
+                    .iter()
+                    .map(|&j| repos[j].name.clone())
+                    .collect(),
+            );
+        }
     }
     None
 }
 
+/// Give each section a display name: the explicit `name =` if set and not
+/// used by another section, otherwise the shortest path suffix that no other
+/// repo shares.
+fn derive_names(sections: &[String], explicit: &[Option<String>]) -> Vec<String> {
+    let explicit: Vec<Option<String>> = explicit
+        .iter()
+        .zip(sections)
+        .map(|(name, section)| {
+            let name = name.as_ref()?;
+            if explicit.iter().filter(|n| n.as_ref() == Some(name)).count() > 1 {
+                eprintln!("warning: [{}] duplicate name: {}", section, name);
+                return None;
+            }
+            Some(name.clone())
+        })
+        .collect();
+    let components: Vec<Vec<&str>> = sections
+        .iter()
+        .map(|s| s.split('/').filter(|c| !c.is_empty()).collect())
+        .collect();
+    let suffix = |i: usize, k: usize| {
+        let comps = &components[i];
+        comps[comps.len().saturating_sub(k)..].join("/")
+    };
+
+    (0..sections.len())
+        .map(|i| {
+            if let Some(name) = &explicit[i] {
+                return name.clone();
+            }
+            (1..=components[i].len())
+                .map(|k| suffix(i, k))
+                .find(|candidate| {
+                    (0..sections.len()).all(|j| {
+                        j == i
+                            || match &explicit[j] {
+                                Some(name) => name != candidate,
+                                None => suffix(j, candidate.matches('/').count() + 1) != *candidate,
+                            }
+                    })
+                })
+                .unwrap_or_else(|| sections[i].clone())
+        })
+        .collect()
+}
+
+/// Find the `git clone`, `hg clone` or `jj git clone` in a checkout command
+/// and parse its arguments. Returns `Ok(None)` if it doesn't clone anything.
+fn parse_checkout(checkout_cmd: &str) -> Result<Option<(Vcs, CloneSpec)>, String> {
+    for words in shellwords::split_commands(checkout_cmd)? {
+        let Some(pos) = words.iter().position(|w| w == "clone") else {
+            continue;
+        };
+        let vcs = match words.first().map(|w| w.as_str()) {
+            Some("git") => Vcs::Git,
+            Some("hg") => Vcs::Hg,
+            Some("jj") if words.get(pos - 1).is_some_and(|w| w == "git") => Vcs::Jj,
+            _ => continue,
+        };
+        return parse_clone_args(&words[pos + 1..]).map(|spec| Some((vcs, spec)));
+    }
+    Ok(None)
+}
+
+fn parse_clone_args(args: &[String]) -> Result<CloneSpec, String> {
+    let mut spec = CloneSpec::default();
+    let mut positional: Vec<String> = Vec::new();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            positional.extend(iter.by_ref().cloned());
+            break;
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            positional.push(arg.clone());
+            continue;
+        }
+
+        // Split `--opt=value`, or take the value from the next word
+        let (opt, inline_value) = match arg.split_once('=') {
+            Some((o, v)) if o.starts_with("--") => (o, Some(v.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let value = if inline_value.is_some() {
+            inline_value
+        } else if CLONE_OPTS_WITH_VALUE.contains(&opt) {
+            Some(
+                iter.next()
+                    .ok_or_else(|| format!("{} needs a value", opt))?
+                    .clone(),
+            )
+        } else {
+            None
+        };
+
+        match (opt, value) {
+            ("-b" | "--branch", Some(v)) => spec.branch = Some(v),
+            ("--depth", Some(v)) => {
+                spec.depth = Some(v.parse().map_err(|_| format!("invalid depth: {}", v))?)
+            }
+            ("--filter", Some(v)) => spec.filter = Some(v),
+            ("--reference", Some(v)) => spec.reference = Some(PathBuf::from(v)),
+            ("--single-branch", None) => spec.single_branch = true,
+            ("--recurse-submodules" | "--recursive", None) => spec.recurse_submodules = true,
+            ("--dissociate", None) => spec.dissociate = true,
+            (opt, Some(v)) if opt.starts_with("--") => {
+                spec.extra_args.push(format!("{}={}", opt, v))
+            }
+            (opt, Some(v)) => {
+                spec.extra_args.push(opt.to_string());
+                spec.extra_args.push(v);
+            }
+            (opt, None) => spec.extra_args.push(opt.to_string()),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    spec.url = positional
+        .next()
+        .ok_or_else(|| "git clone without a URL".to_string())?;
+    spec.dest = positional.next();
+    if let Some(extra) = positional.next() {
+        return Err(format!(
+            "unexpected argument after the directory: {}",
+            extra
+        ));
+    }
+    Ok(spec)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn names(sections: &[&str], explicit: &[Option<&str>]) -> Vec<String> {
+        let sections: Vec<String> = sections.iter().map(|s| s.to_string()).collect();
+        let explicit: Vec<Option<String>> =
+            explicit.iter().map(|n| n.map(|n| n.to_string())).collect();
+        derive_names(&sections, &explicit)
+    }
+
+    #[test]
+    fn test_derive_names_shortest_unique_suffix() {
+        assert_eq!(
+            names(
+                &["team-a/api", "team-b/api", "team-b/web", "x/team-a/api"],
+                &[None, None, None, None]
+            ),
+            vec!["team-a/api", "team-b/api", "web", "x/team-a/api"]
+        );
+    }
+
+    #[test]
+    fn test_derive_names_explicit_name() {
+        assert_eq!(
+            names(&["a/api", "b/api", "c/web"], &[Some("web"), None, None]),
+            vec!["web", "api", "c/web"]
+        );
+    }
+
+    #[test]
+    fn test_derive_names_duplicate_explicit_name() {
+        assert_eq!(
+            names(
+                &["a/api", "b/api", "c/web"],
+                &[Some("api"), Some("api"), Some("site")]
+            ),
+            vec!["a/api", "b/api", "site"]
+        );
+    }
+
+    #[test]
+    fn test_parse_config_keeps_section_case() {
+        let tmp = crate::testutil::TempDir::new("section-case");
+        let config_path = tmp.path().join(".mrconfig");
+        std::fs::write(
+            &config_path,
+            "[src/MyRepo]\ncheckout = git clone 'url' 'MyRepo'\n\n\
+             [src/app]\ndepends = src/MyRepo\n",
+        )
+        .unwrap();
+        let repos = parse_config(&config_path, tmp.path());
+        assert_eq!(repos[0].section, "src/MyRepo");
+        assert_eq!(repos[0].path, tmp.path().join("src/MyRepo"));
+        assert_eq!(repos[1].depends, vec!["src/MyRepo"]);
+    }
+
     #[test]
-    fn test_extract_clone_url_https() {
+    fn test_url_host() {
+        let host = |url| url_host(url);
+        assert_eq!(
+            host("git@github.com:org/repo.git").as_deref(),
+            Some("github.com")
+        );
+        assert_eq!(
+            host("https://GitLab.example.com/org/repo").as_deref(),
+            Some("gitlab.example.com")
+        );
+        assert_eq!(
+            host("ssh://git@gitlab.example.com:2222/org/repo.git").as_deref(),
+            Some("gitlab.example.com")
+        );
+        assert_eq!(host("/srv/git/repo.git"), None);
+        assert_eq!(host("file:///srv/git/repo.git"), None);
+        assert_eq!(host("../repo"), None);
+    }
+
+    #[test]
+    fn test_parse_settings() {
+        let tmp = crate::testutil::TempDir::new("settings");
+        let config_path = tmp.path().join(".mrconfig");
+        std::fs::write(
+            &config_path,
+            "[mrx]\nhost_jobs = 4\nhost_jobs.GitHub.com = 2\nhost_jobs.bad = 0\n\
+             open_command = tig\nrefresh_after_open = no\ntheme = mono\n\
+             color.failed = ff0000\nkey.quit = Q\n\n[repo]\ncheckout = x\n",
+        )
+        .unwrap();
+
+        let settings = parse_settings(&config_path);
+        assert_eq!(settings.host_jobs, Some(4));
+        assert_eq!(
+            settings.host_limits,
+            BTreeMap::from([("github.com".to_string(), 2)])
+        );
+        assert_eq!(settings.open_command(), "tig");
+        assert!(!settings.refresh_after_open());
+        assert_eq!(settings.theme.as_deref(), Some("mono"));
+        assert_eq!(settings.colors["failed"], "ff0000");
+        assert_eq!(settings.keys["quit"], "Q");
+
+        let missing = parse_settings(&tmp.path().join("missing"));
+        assert!(!missing.has_host_limits());
+        assert_eq!(missing.open_command(), "lazygit");
+        assert!(missing.refresh_after_open());
+    }
+
+    #[test]
+    fn test_host_limit() {
+        let settings = Settings {
+            host_limits: BTreeMap::from([("github.com".to_string(), 2)]),
+            ..Default::default()
+        };
+        assert!(settings.has_host_limits());
+        assert_eq!(settings.host_limit("GitHub.com"), Some(2));
+        assert_eq!(settings.host_limit("gitlab.com"), None);
+
+        let settings = Settings {
+            host_jobs: Some(4),
+            ..settings
+        };
+        assert_eq!(settings.host_limit("github.com"), Some(2));
+        assert_eq!(settings.host_limit("gitlab.com"), Some(4));
+    }
+
+    #[test]
+    fn test_find_cycle() {
+        let repo = |section: &str, depends: &[&str]| Repo {
+            name: section.to_string(),
+            section: section.to_string(),
+            path: PathBuf::from(section),
+            clone: None,
+            submodules: false,
+            vcs: Vcs::Git,
+            depends: depends.iter().map(|d| d.to_string()).collect(),
+            group: None,
+        };
+        let mut repos = vec![
+            repo("proto", &[]),
+            repo("api", &["proto"]),
+            repo("web", &["api", "proto"]),
+        ];
+        assert_eq!(find_cycle(&repos), None);
+
+        repos[0].depends = vec!["web".into()];
+        assert_eq!(
+            find_cycle(&repos),
+            Some(vec![
+                "proto".into(),
+                "web".into(),
+                "api".into(),
+                "proto".into()
+            ])
+        );
+    }
+
+    fn spec(cmd: &str) -> CloneSpec {
+        parse_checkout(cmd).unwrap().unwrap().1
+    }
+
+    #[test]
+    fn test_parse_checkout_https() {
         let cmd = "git clone 'https://github.com/mr-yum/bill-api' 'bill-api'";
         assert_eq!(
-            extract_clone_url(cmd),
-            Some("https://github.com/mr-yum/bill-api".to_string())
+            spec(cmd),
+            CloneSpec {
+                url: "https://github.com/mr-yum/bill-api".into(),
+                dest: Some("bill-api".into()),
+                ..Default::default()
+            }
         );
     }
 
     #[test]
-    fn test_extract_clone_url_ssh() {
+    fn test_parse_checkout_ssh() {
         let cmd = "git clone 'git@github.com:mr-yum/cli.git' 'cli'";
+        assert_eq!(spec(cmd).url, "git@github.com:mr-yum/cli.git");
+    }
+
+    #[test]
+    fn test_parse_checkout_with_options() {
+        let cmd = "git clone --depth 1 -b develop --recurse-submodules \
+                   -c core.autocrlf=false --filter=blob:none 'file:///my repos/a' a && cd a";
+        assert_eq!(
+            spec(cmd),
+            CloneSpec {
+                url: "file:///my repos/a".into(),
+                dest: Some("a".into()),
+                branch: Some("develop".into()),
+                depth: Some(1),
+                filter: Some("blob:none".into()),
+                recurse_submodules: true,
+                extra_args: vec!["-c".into(), "core.autocrlf=false".into()],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_checkout_other_vcs() {
+        let (vcs, spec) = parse_checkout("hg clone https://hg.example.com/repo repo")
+            .unwrap()
+            .unwrap();
+        assert_eq!(vcs, Vcs::Hg);
+        assert_eq!(spec.url, "https://hg.example.com/repo");
+        let (vcs, _) = parse_checkout("jj git clone --colocate git@github.com:org/r.git r")
+            .unwrap()
+            .unwrap();
+        assert_eq!(vcs, Vcs::Jj);
+    }
+
+    #[test]
+    fn test_parse_checkout_without_clone() {
+        assert_eq!(parse_checkout("mkdir foo && echo clone"), Ok(None));
+        assert!(parse_checkout("git clone --depth").is_err());
+    }
+
+    #[test]
+    fn test_parse_checkout_multiline() {
+        let (_, spec) = parse_checkout("git clone 'url' 'dir'\ncd dir\ngit config x y")
+            .unwrap()
+            .unwrap();
+        assert_eq!(spec.url, "url");
+        assert_eq!(spec.dest.as_deref(), Some("dir"));
+        assert!(spec.extra_args.is_empty());
+        assert!(parse_checkout("git clone url dir cd dir").is_err());
+    }
+
+    #[test]
+    fn test_section_clone_opts_override_checkout() {
+        let mut ini = configparser::ini::Ini::new_cs();
+        ini.read(
+            "[a]\ncheckout = git clone --depth 5 url a\ndepth = 1\nsingle_branch = true\nreference = mirrors/a.git\n"
+                .into(),
+        )
+        .unwrap();
+        let mut spec = spec("git clone --depth 5 url a");
+        apply_section_clone_opts(&mut spec, &ini, "a", Path::new("/repos")).unwrap();
+        assert_eq!(spec.depth, Some(1));
+        assert!(spec.single_branch);
+        assert_eq!(spec.reference, Some(PathBuf::from("/repos/mirrors/a.git")));
+    }
+
+    #[test]
+    fn test_checkout_reference_is_relative_to_base_dir() {
+        let ini = configparser::ini::Ini::new_cs();
+        let reference = |cmd| {
+            let mut spec = spec(cmd);
+            apply_section_clone_opts(&mut spec, &ini, "a", Path::new("/repos")).unwrap();
+            spec.reference
+        };
+        assert_eq!(
+            reference("git clone --reference mirrors/a.git url a"),
+            Some(PathBuf::from("/repos/mirrors/a.git"))
+        );
+        assert_eq!(
+            reference("git clone --reference /srv/a.git url a"),
+            Some(PathBuf::from("/srv/a.git"))
+        );
+    }
+
+    #[test]
+    fn test_clone_spec_args() {
+        let spec = spec("git clone --branch=main --depth=5 -q url dir");
         assert_eq!(
-            extract_clone_url(cmd),
-            Some("git@github.com:mr-yum/cli.git".to_string())
+            spec.args(Path::new("/repos/dir")),
+            vec![
+                "clone",
+                "-q",
+                "--branch",
+                "main",
+                "--depth=5",
+                "--",
+                "url",
+                "/repos/dir"
+            ]
         );
     }
 }
@@ -0,0 +1,335 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// A line-preserving view of an `.mrconfig` file.
+///
+/// Unlike `configparser`, this keeps comments, blank lines, key order and
+/// formatting intact so the file can be edited in place and written back.
+pub struct ConfigFile {
+    path: PathBuf,
+    lines: Vec<String>,
+}
+
+/// Line range `[start, end)` of a section, header included.
+struct SectionSpan {
+    start: usize,
+    end: usize,
+}
+
+impl ConfigFile {
+    /// Load a config file. A missing file is treated as empty.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self::parse(path, &content))
+    }
+
+    fn parse(path: &Path, content: &str) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            lines: content.lines().map(|l| l.to_string()).collect(),
+        }
+    }
+
+    pub fn sections(&self) -> Vec<String> {
+        self.lines
+            .iter()
+            .filter_map(|l| section_name(l))
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    pub fn has_section(&self, section: &str) -> bool {
+        self.find_section(section).is_some()
+    }
+
+    /// Get the value of `key` in `section`, joining continuation lines.
+    pub fn get(&self, section: &str, key: &str) -> Option<String> {
+        let span = self.find_section(section)?;
+        let (start, end) = self.find_key(&span, key)?;
+        let (_, first) = split_key(&self.lines[start])?;
+        let mut value = first.to_string();
+        for line in &self.lines[start + 1..end] {
+            value.push('\n');
+            value.push_str(line.trim());
+        }
+        Some(value)
+    }
+
+    /// Set `key` in `section`, replacing an existing value in place or
+    /// appending a new key. The section is created if it doesn't exist.
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        let new_lines = format_entry(key, value);
+
+        let span = match self.find_section(section) {
+            Some(span) => span,
+            None => {
+                self.push_section(section, new_lines);
+                return;
+            }
+        };
+
+        if let Some((start, end)) = self.find_key(&span, key) {
+            self.lines.splice(start..end, new_lines);
+            return;
+        }
+
+        // Insert after the last key of the section, before any trailing
+        // blank lines or comments that belong to the next section.
+        let insert_at = (span.start + 1..span.end)
+            .rev()
+            .find(|&i| is_entry_line(&self.lines[i]))
+            .map(|i| i + 1)
+            .unwrap_or(span.start + 1);
+        self.lines.splice(insert_at..insert_at, new_lines);
+    }
+
+    /// Add an empty section if it doesn't exist yet.
+    pub fn add_section(&mut self, section: &str) {
+        if !self.has_section(section) {
+            self.push_section(section, Vec::new());
+        }
+    }
+
+    /// Remove a section and its keys. Returns false if it wasn't present.
+    pub fn remove_section(&mut self, section: &str) -> bool {
+        let span = match self.find_section(section) {
+            Some(span) => span,
+            None => return false,
+        };
+
+        // Comments directly above a header describe that section: take ours
+        // with us and leave the next section's in place. A comment block at
+        // the top of the file is a file header, so it stays.
+        let mut start = span.start;
+        while start > 0 && is_comment(&self.lines[start - 1]) {
+            start -= 1;
+        }
+        if start == 0 {
+            start = span.start;
+        }
+        let mut end = span.end;
+        if end < self.lines.len() {
+            while end > span.start + 1 && is_comment(&self.lines[end - 1]) {
+                end -= 1;
+            }
+        }
+        self.lines.drain(start..end);
+
+        while self.lines.last().is_some_and(|l| l.trim().is_empty()) {
+            self.lines.pop();
+        }
+        true
+    }
+
+    /// Write the file atomically: write a temp file next to it, then rename.
+    /// A symlinked config is followed, so the link stays in place.
+    pub fn save(&self) -> io::Result<()> {
+        let path = std::fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone());
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "mrconfig".into());
+        let tmp_path = path.with_file_name(format!(".{}.tmp", file_name));
+
+        let result = (|| {
+            let mut file = std::fs::File::create(&tmp_path)?;
+            file.write_all(self.to_string().as_bytes())?;
+            file.sync_all()?;
+            if let Ok(meta) = std::fs::metadata(&path) {
+                std::fs::set_permissions(&tmp_path, meta.permissions())?;
+            }
+            std::fs::rename(&tmp_path, &path)
+        })();
+
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp_path);
+        }
+        result
+    }
+
+    fn push_section(&mut self, section: &str, entries: Vec<String>) {
+        if self.lines.last().is_some_and(|l| !l.trim().is_empty()) {
+            self.lines.push(String::new());
+        }
+        self.lines.push(format!("[{}]", section));
+        self.lines.extend(entries);
+    }
+
+    fn find_section(&self, section: &str) -> Option<SectionSpan> {
+        let start = self
+            .lines
+            .iter()
+            .position(|l| section_name(l) == Some(section))?;
+        let end = self.lines[start + 1..]
+            .iter()
+            .position(|l| section_name(l).is_some())
+            .map(|p| start + 1 + p)
+            .unwrap_or(self.lines.len());
+        Some(SectionSpan { start, end })
+    }
+
+    /// Line range of `key` within a section, continuation lines included.
+    fn find_key(&self, span: &SectionSpan, key: &str) -> Option<(usize, usize)> {
+        let start = (span.start + 1..span.end).find(|&i| {
+            !is_continuation(&self.lines[i])
+                && split_key(&self.lines[i]).is_some_and(|(k, _)| k == key)
+        })?;
+        let end = (start + 1..span.end)
+            .find(|&i| !is_continuation(&self.lines[i]))
+            .unwrap_or(span.end);
+        Some((start, end))
+    }
+}
+
+impl fmt::Display for ConfigFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+fn section_name(line: &str) -> Option<&str> {
+    let trimmed = line.trim();
+    trimmed
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .map(|s| s.trim())
+}
+
+fn is_comment(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.starts_with('#') || trimmed.starts_with(';')
+}
+
+fn is_continuation(line: &str) -> bool {
+    line.starts_with(|c: char| c.is_whitespace()) && !line.trim().is_empty() && !is_comment(line)
+}
+
+fn is_entry_line(line: &str) -> bool {
+    !line.trim().is_empty() && !is_comment(line)
+}
+
+fn split_key(line: &str) -> Option<(&str, &str)> {
+    if is_comment(line) {
+        return None;
+    }
+    let pos = line.find(['=', ':'])?;
+    Some((line[..pos].trim(), line[pos + 1..].trim()))
+}
+
+fn format_entry(key: &str, value: &str) -> Vec<String> {
+    let mut value_lines = value.lines();
+    let first = value_lines.next().unwrap_or("");
+    let mut lines = vec![format!("{} = {}", key, first)];
+    lines.extend(value_lines.map(|l| format!("  {}", l)));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> ConfigFile {
+        ConfigFile::parse(Path::new("/tmp/.mrconfig"), content)
+    }
+
+    #[test]
+    fn test_commented_section_is_not_a_section() {
+        let cfg = parse("# [repos/a]\n; [repos/b]\n[repos/c]\ncheckout = x\n");
+        assert!(!cfg.has_section("repos/a"));
+        assert!(!cfg.has_section("repos/b"));
+        assert!(cfg.has_section("repos/c"));
+    }
+
+    #[test]
+    fn test_add_section_without_trailing_newline() {
+        let mut cfg = parse("[repos/a]\ncheckout = git clone 'a' 'a'");
+        cfg.set("repos/b", "checkout", "git clone 'b' 'b'");
+        assert_eq!(
+            cfg.to_string(),
+            "[repos/a]\ncheckout = git clone 'a' 'a'\n\n[repos/b]\ncheckout = git clone 'b' 'b'\n"
+        );
+    }
+
+    #[test]
+    fn test_update_preserves_comments_and_order() {
+        let mut cfg = parse(
+            "# my repos\n[repos/a]\n# pinned\ncheckout = old\n  continued\nskip = true\n\n[repos/b]\ncheckout = b\n",
+        );
+        cfg.set("repos/a", "checkout", "new");
+        assert_eq!(
+            cfg.to_string(),
+            "# my repos\n[repos/a]\n# pinned\ncheckout = new\nskip = true\n\n[repos/b]\ncheckout = b\n"
+        );
+    }
+
+    #[test]
+    fn test_set_new_key_goes_after_last_key() {
+        let mut cfg = parse("[repos/a]\ncheckout = a\n\n# next\n[repos/b]\ncheckout = b\n");
+        cfg.set("repos/a", "skip", "true");
+        assert_eq!(
+            cfg.to_string(),
+            "[repos/a]\ncheckout = a\nskip = true\n\n# next\n[repos/b]\ncheckout = b\n"
+        );
+    }
+
+    #[test]
+    fn test_get_joins_continuation_lines() {
+        let cfg = parse("[repos/a]\nupdate = git pull\n  git submodule update\n");
+        assert_eq!(
+            cfg.get("repos/a", "update"),
+            Some("git pull\ngit submodule update".to_string())
+        );
+    }
+
+    #[test]
+    fn test_remove_section_keeps_neighbours() {
+        let mut cfg = parse(
+            "[repos/a]\ncheckout = a\n\n[repos/b]\ncheckout = b\n\n# about c\n[repos/c]\ncheckout = c\n",
+        );
+        assert!(cfg.remove_section("repos/b"));
+        assert_eq!(
+            cfg.to_string(),
+            "[repos/a]\ncheckout = a\n\n# about c\n[repos/c]\ncheckout = c\n"
+        );
+        assert!(cfg.remove_section("repos/c"));
+        assert_eq!(cfg.to_string(), "[repos/a]\ncheckout = a\n");
+        assert!(cfg.remove_section("repos/a"));
+        assert_eq!(cfg.to_string(), "");
+        assert!(!cfg.remove_section("repos/missing"));
+    }
+
+    #[test]
+    fn test_remove_section_keeps_file_header() {
+        let mut cfg = parse("# header\n[repos/a]\ncheckout = a\n");
+        assert!(cfg.remove_section("repos/a"));
+        assert_eq!(cfg.to_string(), "# header\n");
+    }
+
+    #[test]
+    fn test_save_through_symlink() {
+        let tmp = crate::testutil::TempDir::new("config-symlink");
+        let real = tmp.path().join("dotfiles/mrconfig");
+        std::fs::create_dir_all(real.parent().unwrap()).unwrap();
+        std::fs::write(&real, "[repos/a]\ncheckout = x\n").unwrap();
+        let link = tmp.path().join(".mrconfig");
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        let mut cfg = ConfigFile::load(&link).unwrap();
+        cfg.set("repos/b", "checkout", "y");
+        cfg.save().unwrap();
+
+        assert!(std::fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(
+            std::fs::read_to_string(&real).unwrap(),
+            "[repos/a]\ncheckout = x\n\n[repos/b]\ncheckout = y\n"
+        );
+    }
+}
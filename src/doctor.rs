@@ -0,0 +1,290 @@
+use crate::config::{find_cycle, Repo};
+use crate::register::{absolute_path, find_git_repos};
+use crate::vcs::Vcs;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    /// Configured, but the directory doesn't exist
+    MissingCheckout { path: PathBuf },
+    /// Configured and the directory exists, but isn't a repo of any
+    /// supported VCS (and isn't marked `vcs = plain`)
+    NotRepo { path: PathBuf },
+    /// A git repo under the base dir that isn't in the config
+    Unregistered { path: PathBuf },
+    /// A configured repo inside another configured repo
+    Nested { outer: PathBuf, inner: PathBuf },
+    /// Several sections resolving to the same directory
+    DuplicatePath { path: PathBuf, count: usize },
+    /// Several sections cloning the same URL
+    DuplicateUrl { url: String, paths: Vec<PathBuf> },
+    /// Repos depending on each other in a loop, first name repeated at the end
+    DependencyCycle { names: Vec<String> },
+}
+
+impl Problem {
+    fn category(&self) -> &'static str {
+        match self {
+            Problem::MissingCheckout { .. } => "missing checkouts",
+            Problem::NotRepo { .. } => "not repositories",
+            Problem::Unregistered { .. } => "unregistered repos",
+            Problem::Nested { .. } => "nested repos",
+            Problem::DuplicatePath { .. } => "duplicate paths",
+            Problem::DuplicateUrl { .. } => "duplicate clone URLs",
+            Problem::DependencyCycle { .. } => "dependency cycles",
+        }
+    }
+
+    fn describe(&self, base_dir: &Path) -> String {
+        let rel = |p: &PathBuf| {
+            p.strip_prefix(base_dir)
+                .unwrap_or(p)
+                .to_string_lossy()
+                .to_string()
+        };
+        match self {
+            Problem::MissingCheckout { path }
+            | Problem::NotRepo { path }
+            | Problem::Unregistered { path } => rel(path),
+            Problem::Nested { outer, inner } => format!("{} inside {}", rel(inner), rel(outer)),
+            Problem::DuplicatePath { path, count } => {
+                format!("{} ({} sections)", rel(path), count)
+            }
+            Problem::DuplicateUrl { url, paths } => format!(
+                "{}: {}",
+                url,
+                paths.iter().map(rel).collect::<Vec<_>>().join(", ")
+            ),
+            Problem::DependencyCycle { names } => names.join(" -> "),
+        }
+    }
+}
+
+/// Cross-check the config against the filesystem and print a report.
+/// Returns false if any problems were found.
+pub fn run(repos: &[Repo], base_dir: &Path) -> bool {
+    let base_dir = absolute_path(base_dir);
+    let mut problems = check_config(repos);
+    problems.extend(check_filesystem(repos, &base_dir));
+
+    if problems.is_empty() {
+        println!("no problems found ({} repos)", repos.len());
+        return true;
+    }
+
+    let mut by_category: BTreeMap<&str, Vec<&Problem>> = BTreeMap::new();
+    for p in &problems {
+        by_category.entry(p.category()).or_default().push(p);
+    }
+    for (category, items) in &by_category {
+        println!("{} ({}):", category, items.len());
+        for p in items {
+            println!("  {}", p.describe(&base_dir));
+        }
+    }
+    println!(
+        "{} problem{} found",
+        problems.len(),
+        if problems.len() == 1 { "" } else { "s" }
+    );
+    false
+}
+
+/// Problems visible from the config alone: nesting, duplicates and
+/// dependency cycles
+fn check_config(repos: &[Repo]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let paths: Vec<PathBuf> = repos.iter().map(|r| absolute_path(&r.path)).collect();
+
+    let mut path_counts: BTreeMap<&PathBuf, usize> = BTreeMap::new();
+    for p in &paths {
+        *path_counts.entry(p).or_default() += 1;
+    }
+    for (path, count) in &path_counts {
+        if *count > 1 {
+            problems.push(Problem::DuplicatePath {
+                path: (*path).clone(),
+                count: *count,
+            });
+        }
+    }
+
+    let unique: Vec<&PathBuf> = path_counts.keys().copied().collect();
+    for outer in &unique {
+        for inner in &unique {
+            if inner != outer && inner.starts_with(outer) {
+                problems.push(Problem::Nested {
+                    outer: (*outer).clone(),
+                    inner: (*inner).clone(),
+                });
+            }
+        }
+    }
+
+    let mut by_url: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for (repo, path) in repos.iter().zip(&paths) {
+        if let Some(url) = repo.clone_url() {
+            by_url
+                .entry(normalize_url(url))
+                .or_default()
+                .push(path.clone());
+        }
+    }
+    for (url, mut paths) in by_url {
+        paths.sort();
+        paths.dedup();
+        if paths.len() > 1 {
+            problems.push(Problem::DuplicateUrl { url, paths });
+        }
+    }
+
+    if let Some(names) = find_cycle(repos) {
+        problems.push(Problem::DependencyCycle { names });
+    }
+
+    problems
+}
+
+/// Problems that need the filesystem: missing, non-git and unregistered dirs
+fn check_filesystem(repos: &[Repo], base_dir: &Path) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let configured: HashSet<PathBuf> = repos.iter().map(|r| absolute_path(&r.path)).collect();
+
+    let mut checked: HashSet<PathBuf> = HashSet::new();
+    for repo in repos {
+        let path = absolute_path(&repo.path);
+        if !checked.insert(path.clone()) {
+            continue;
+        }
+        if !path.is_dir() {
+            problems.push(Problem::MissingCheckout { path });
+        } else if repo.vcs != Vcs::Plain && Vcs::detect(&path).is_none() {
+            problems.push(Problem::NotRepo { path });
+        }
+    }
+
+    for path in find_git_repos(base_dir) {
+        if path != base_dir && !configured.contains(&path) {
+            problems.push(Problem::Unregistered { path });
+        }
+    }
+
+    problems
+}
+
+/// Normalize a clone URL so trivially different spellings compare equal
+fn normalize_url(url: &str) -> String {
+    let url = url.trim().trim_end_matches('/');
+    url.strip_suffix(".git").unwrap_or(url).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CloneSpec;
+    use crate::testutil::{init_repo, TempDir};
+
+    fn repo(path: &str, url: Option<&str>) -> Repo {
+        Repo {
+            name: path.rsplit('/').next().unwrap().to_string(),
+            section: path.trim_start_matches('/').to_string(),
+            path: PathBuf::from(path),
+            clone: url.map(|u| CloneSpec {
+                url: u.to_string(),
+                ..Default::default()
+            }),
+            submodules: false,
+            vcs: Vcs::Git,
+            depends: Vec::new(),
+            group: None,
+        }
+    }
+
+    #[test]
+    fn test_check_config_finds_nested_and_duplicates() {
+        let repos = vec![
+            repo("/nonexistent/a", Some("git@github.com:org/a.git")),
+            repo("/nonexistent/a/sub", Some("git@github.com:org/sub")),
+            repo("/nonexistent/b", Some("git@github.com:org/a")),
+            repo("/nonexistent/./b", None),
+        ];
+        let problems = check_config(&repos);
+        assert_eq!(
+            problems,
+            vec![
+                Problem::DuplicatePath {
+                    path: PathBuf::from("/nonexistent/b"),
+                    count: 2,
+                },
+                Problem::Nested {
+                    outer: PathBuf::from("/nonexistent/a"),
+                    inner: PathBuf::from("/nonexistent/a/sub"),
+                },
+                Problem::DuplicateUrl {
+                    url: "git@github.com:org/a".into(),
+                    paths: vec![
+                        PathBuf::from("/nonexistent/a"),
+                        PathBuf::from("/nonexistent/b"),
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_config_finds_dependency_cycle() {
+        let mut repos = vec![repo("/nonexistent/a", None), repo("/nonexistent/b", None)];
+        repos[0].depends = vec!["nonexistent/b".into()];
+        repos[1].depends = vec!["nonexistent/a".into()];
+        assert_eq!(
+            check_config(&repos),
+            vec![Problem::DependencyCycle {
+                names: vec!["a".into(), "b".into(), "a".into()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_check_filesystem_finds_nested_unregistered_repos() {
+        let tmp = TempDir::new("doctor");
+        let base = tmp.path();
+        init_repo(base);
+        init_repo(&base.join("a"));
+        init_repo(&base.join("a/vendor/lib"));
+        init_repo(&base.join("group/b"));
+        std::fs::create_dir_all(base.join("a/sub")).unwrap();
+        std::fs::write(base.join("a/sub/.git"), "gitdir: ../.git/modules/sub\n").unwrap();
+
+        let path = base.join("a").to_string_lossy().to_string();
+        let problems = check_filesystem(&[repo(&path, None)], base);
+        assert_eq!(
+            problems,
+            vec![
+                Problem::Unregistered {
+                    path: base.join("a/vendor/lib"),
+                },
+                Problem::Unregistered {
+                    path: base.join("group/b"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mixed_case_section_is_clean() {
+        let tmp = TempDir::new("doctor-case");
+        let base = tmp.path();
+        init_repo(&base.join("src/MyRepo"));
+        let config_path = base.join(".mrconfig");
+        std::fs::write(
+            &config_path,
+            "[src/MyRepo]\ncheckout = git clone 'url' 'MyRepo'\n",
+        )
+        .unwrap();
+
+        let repos = crate::config::parse_config(&config_path, base);
+        assert_eq!(check_config(&repos), vec![]);
+        assert_eq!(check_filesystem(&repos, base), vec![]);
+    }
+}
@@ -1,12 +1,22 @@
-use crate::config::Repo;
+use crate::config::{url_host, Repo, Settings};
 use crate::operations::Operation;
-use std::process::Stdio;
-use std::sync::Arc;
+use crate::vcs::Vcs;
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::{Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::process::Command;
-use tokio::sync::{mpsc, Semaphore};
+use tokio::sync::{mpsc, watch, Semaphore, SemaphorePermit};
+use tokio::task::JoinSet;
 
 #[derive(Debug, Clone)]
 pub enum TaskEvent {
+    /// Waiting for the named dependencies; empty once they have all finished
+    Blocked {
+        index: usize,
+        on: Vec<String>,
+    },
     Started {
         index: usize,
     },
@@ -15,6 +25,8 @@ pub enum TaskEvent {
         stdout: String,
         stderr: String,
         exit_code: i32,
+        /// Time from start to finish, not counting waiting for a job slot
+        duration: Duration,
     },
     Skipped {
         index: usize,
@@ -22,128 +34,516 @@ pub enum TaskEvent {
     },
 }
 
+impl TaskEvent {
+    pub fn index_mut(&mut self) -> &mut usize {
+        match self {
+            TaskEvent::Blocked { index, .. }
+            | TaskEvent::Started { index }
+            | TaskEvent::Finished { index, .. }
+            | TaskEvent::Skipped { index, .. } => index,
+        }
+    }
+}
+
+/// Job slots for a session: the `-j` limit and the per-host limits on
+/// remote operations. Clones share the slots, so runs started later in the
+/// session count against the same limits as the first.
+#[derive(Clone)]
+pub struct Limits {
+    jobs: Arc<Semaphore>,
+    hosts: Arc<HostLimits>,
+}
+
+impl Limits {
+    pub fn new(max_jobs: usize, settings: &Settings) -> Limits {
+        Limits {
+            jobs: Arc::new(Semaphore::new(max_jobs)),
+            hosts: Arc::new(HostLimits {
+                settings: settings.clone(),
+                semaphores: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+}
+
+/// Run every operation within `limits`: each takes a job slot, and
+/// operations that contact a remote also take one of their host's. A repo
+/// with `depends` waits until those repos have finished; if one of them
+/// fails or is skipped, it's skipped too.
 pub fn execute_all(
-    _repos: &[Repo],
+    repos: &[Repo],
     operations: Vec<Operation>,
-    max_jobs: usize,
+    limits: &Limits,
 ) -> mpsc::UnboundedReceiver<TaskEvent> {
     let (tx, rx) = mpsc::unbounded_channel();
-    let semaphore = Arc::new(Semaphore::new(max_jobs));
+    let has_host_limits = limits.hosts.settings.has_host_limits();
 
-    for (i, op) in operations.into_iter().enumerate() {
+    // Each task publishes `Some(success)` when it's done. Only a run that
+    // exits 0 counts: a skipped dependency didn't do its part either.
+    let (done_txs, done_rxs): (Vec<_>, Vec<_>) = (0..operations.len())
+        .map(|_| watch::channel(None::<bool>))
+        .unzip();
+
+    for ((i, op), done_tx) in operations.into_iter().enumerate().zip(done_txs) {
         let tx = tx.clone();
-        let sem = semaphore.clone();
+        let sem = limits.jobs.clone();
+        let remote = (has_host_limits && op.uses_remote()).then(|| {
+            (
+                limits.hosts.clone(),
+                repos[i].clone_url().map(String::from),
+                repos[i].vcs,
+                repos[i].path.clone(),
+            )
+        });
+        let deps: Vec<(String, watch::Receiver<Option<bool>>)> = repos
+            .iter()
+            .enumerate()
+            .filter(|(j, r)| *j != i && repos[i].depends.contains(&r.section))
+            .map(|(j, r)| (r.name.clone(), done_rxs[j].clone()))
+            .collect();
 
         tokio::spawn(async move {
-            match op {
-                Operation::Skip { reason } => {
-                    let _ = tx.send(TaskEvent::Skipped { index: i, reason });
-                }
-                Operation::NotCheckedOut => {
-                    let _ = tx.send(TaskEvent::Skipped {
-                        index: i,
-                        reason: "not checked out".into(),
-                    });
-                }
-                Operation::Git { args, work_dir } => {
-                    let _permit = sem.acquire().await.unwrap();
-                    let _ = tx.send(TaskEvent::Started { index: i });
-                    let result = Command::new("git")
-                        .args(&args)
-                        .current_dir(&work_dir)
-                        .env("GIT_TERMINAL_PROMPT", "0")
-                        .env("GIT_PAGER", "cat")
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::piped())
-                        .output()
-                        .await;
-
-                    match result {
-                        Ok(output) => {
-                            let _ = tx.send(TaskEvent::Finished {
-                                index: i,
-                                stdout: String::from_utf8_lossy(&output.stdout).to_string(),
-                                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
-                                exit_code: output.status.code().unwrap_or(1),
-                            });
-                        }
-                        Err(e) => {
-                            let _ = tx.send(TaskEvent::Finished {
-                                index: i,
-                                stdout: String::new(),
-                                stderr: format!("failed to execute: {}", e),
-                                exit_code: 1,
-                            });
-                        }
+            if let Some(failed) = wait_for_deps(i, deps, &tx).await {
+                let _ = tx.send(TaskEvent::Skipped {
+                    index: i,
+                    reason: format!("dependency {} didn't succeed", failed),
+                });
+                let _ = done_tx.send(Some(false));
+                return;
+            }
+            let host_sem = match remote {
+                Some((hosts, url, vcs, path)) => hosts.semaphore(url.as_deref(), vcs, &path).await,
+                None => None,
+            };
+            let event = run(i, op, &tx, &sem, host_sem.as_deref()).await;
+            let success = matches!(event, TaskEvent::Finished { exit_code: 0, .. });
+            let _ = tx.send(event);
+            let _ = done_tx.send(Some(success));
+        });
+    }
+
+    rx
+}
+
+/// Per-host limits on remote operations, with a semaphore for each host
+/// created the first time a repo on it runs
+struct HostLimits {
+    settings: Settings,
+    semaphores: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimits {
+    /// Semaphore for the host a repo's remote operations go to, `None` if
+    /// the host is unknown or has no limit
+    async fn semaphore(
+        &self,
+        clone_url: Option<&str>,
+        vcs: Vcs,
+        path: &Path,
+    ) -> Option<Arc<Semaphore>> {
+        let host = remote_host(clone_url, vcs, path).await?;
+        let limit = self.settings.host_limit(&host)?;
+        let mut semaphores = self.semaphores.lock().unwrap();
+        Some(
+            semaphores
+                .entry(host)
+                .or_insert_with(|| Arc::new(Semaphore::new(limit)))
+                .clone(),
+        )
+    }
+}
+
+/// Host a repo's remote operations go to: from its clone URL, or from the
+/// default remote of an existing checkout, asked of the repo's own VCS
+async fn remote_host(clone_url: Option<&str>, vcs: Vcs, path: &Path) -> Option<String> {
+    if let Some(url) = clone_url {
+        return url_host(url);
+    }
+    let args: &[&str] = match vcs {
+        Vcs::Git => &["remote", "get-url", "origin"],
+        Vcs::Hg => &["paths", "default"],
+        Vcs::Jj => &["git", "remote", "list"],
+        Vcs::Plain => return None,
+    };
+    let output = Command::new(vcs.program())
+        .args(args)
+        .current_dir(path)
+        .env("HGPLAIN", "1")
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match vcs {
+        // `jj git remote list` prints `<name> <url>` lines
+        Vcs::Jj => stdout
+            .lines()
+            .find_map(|l| l.strip_prefix("origin "))
+            .and_then(url_host),
+        _ => url_host(stdout.trim()),
+    }
+}
+
+/// Take a slot from the host's limit, then from the global one, so repos
+/// waiting on a busy host don't hold up others
+async fn acquire<'a>(
+    sem: &'a Semaphore,
+    host_sem: Option<&'a Semaphore>,
+) -> (Option<SemaphorePermit<'a>>, SemaphorePermit<'a>) {
+    let host_permit = match host_sem {
+        Some(s) => Some(s.acquire().await.unwrap()),
+        None => None,
+    };
+    (host_permit, sem.acquire().await.unwrap())
+}
+
+/// Wait until every dependency has finished, reporting which ones are still
+/// pending. Returns the name of the first dependency that didn't succeed.
+async fn wait_for_deps(
+    index: usize,
+    deps: Vec<(String, watch::Receiver<Option<bool>>)>,
+    tx: &mpsc::UnboundedSender<TaskEvent>,
+) -> Option<String> {
+    if deps.is_empty() {
+        return None;
+    }
+    let mut pending: Vec<String> = deps.iter().map(|(name, _)| name.clone()).collect();
+    let mut waits = JoinSet::new();
+    for (name, mut done) in deps {
+        waits.spawn(async move {
+            let success = match done.wait_for(|d| d.is_some()).await {
+                Ok(d) => d.unwrap_or(false),
+                Err(_) => false,
+            };
+            (name, success)
+        });
+    }
+
+    loop {
+        let _ = tx.send(TaskEvent::Blocked {
+            index,
+            on: pending.clone(),
+        });
+        match waits.join_next().await {
+            Some(Ok((name, true))) => pending.retain(|p| *p != name),
+            Some(Ok((name, false))) => return Some(name),
+            Some(Err(_)) => return Some(pending.join(", ")),
+            None => return None,
+        }
+    }
+}
+
+/// Run one operation, returning its final `Finished` or `Skipped` event
+async fn run(
+    i: usize,
+    op: Operation,
+    tx: &mpsc::UnboundedSender<TaskEvent>,
+    sem: &Semaphore,
+    host_sem: Option<&Semaphore>,
+) -> TaskEvent {
+    match op {
+        Operation::Skip { reason } => TaskEvent::Skipped { index: i, reason },
+        Operation::NotCheckedOut => TaskEvent::Skipped {
+            index: i,
+            reason: "not checked out".into(),
+        },
+        Operation::Git { args, work_dir } => {
+            let _permits = acquire(sem, host_sem).await;
+            let _ = tx.send(TaskEvent::Started { index: i });
+            let started = Instant::now();
+            let result = Command::new("git")
+                .args(&args)
+                .current_dir(&work_dir)
+                .env("GIT_TERMINAL_PROMPT", "0")
+                .env("GIT_PAGER", "cat")
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .output()
+                .await;
+            finished(i, result, "", started)
+        }
+        Operation::GitSeq { commands, work_dir } => {
+            let _permits = acquire(sem, host_sem).await;
+            let _ = tx.send(TaskEvent::Started { index: i });
+            let started = Instant::now();
+
+            let mut stdout = String::new();
+            let mut stderr = String::new();
+            let mut exit_code = 0;
+            for args in &commands {
+                let result = Command::new("git")
+                    .args(args)
+                    .current_dir(&work_dir)
+                    .env("GIT_TERMINAL_PROMPT", "0")
+                    .env("GIT_PAGER", "cat")
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .output()
+                    .await;
+
+                match result {
+                    Ok(output) => {
+                        stdout.push_str(&String::from_utf8_lossy(&output.stdout));
+                        stderr.push_str(&String::from_utf8_lossy(&output.stderr));
+                        exit_code = output.status.code().unwrap_or(1);
                     }
-                }
-                Operation::Clone { url, dest } => {
-                    let _permit = sem.acquire().await.unwrap();
-                    let _ = tx.send(TaskEvent::Started { index: i });
-
-                    let parent = dest.parent().unwrap_or(&dest);
-                    let _ = tokio::fs::create_dir_all(parent).await;
-
-                    let result = Command::new("git")
-                        .args(["clone", &url, &dest.to_string_lossy()])
-                        .env("GIT_TERMINAL_PROMPT", "0")
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::piped())
-                        .output()
-                        .await;
-
-                    match result {
-                        Ok(output) => {
-                            let _ = tx.send(TaskEvent::Finished {
-                                index: i,
-                                stdout: String::from_utf8_lossy(&output.stdout).to_string(),
-                                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
-                                exit_code: output.status.code().unwrap_or(1),
-                            });
-                        }
-                        Err(e) => {
-                            let _ = tx.send(TaskEvent::Finished {
-                                index: i,
-                                stdout: String::new(),
-                                stderr: format!("failed to execute: {}", e),
-                                exit_code: 1,
-                            });
-                        }
+                    Err(e) => {
+                        stderr.push_str(&format!("failed to execute: {}", e));
+                        exit_code = 1;
                     }
                 }
-                Operation::Shell { cmd, work_dir } => {
-                    let _permit = sem.acquire().await.unwrap();
-                    let _ = tx.send(TaskEvent::Started { index: i });
-                    let result = Command::new("sh")
-                        .args(["-c", &cmd])
-                        .current_dir(&work_dir)
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::piped())
-                        .output()
-                        .await;
-
-                    match result {
-                        Ok(output) => {
-                            let _ = tx.send(TaskEvent::Finished {
-                                index: i,
-                                stdout: String::from_utf8_lossy(&output.stdout).to_string(),
-                                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
-                                exit_code: output.status.code().unwrap_or(1),
-                            });
-                        }
-                        Err(e) => {
-                            let _ = tx.send(TaskEvent::Finished {
-                                index: i,
-                                stdout: String::new(),
-                                stderr: format!("failed to execute: {}", e),
-                                exit_code: 1,
-                            });
-                        }
-                    }
+                if exit_code != 0 {
+                    break;
                 }
             }
-        });
+
+            TaskEvent::Finished {
+                index: i,
+                stdout,
+                stderr,
+                exit_code,
+                duration: started.elapsed(),
+            }
+        }
+        Operation::Exec {
+            program,
+            args,
+            work_dir,
+            ok_codes,
+        } => {
+            let _permits = acquire(sem, host_sem).await;
+            let _ = tx.send(TaskEvent::Started { index: i });
+            let started = Instant::now();
+            let result = Command::new(program)
+                .args(&args)
+                .current_dir(&work_dir)
+                .env("HGPLAIN", "1")
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .output()
+                .await;
+
+            match finished(i, result, program, started) {
+                TaskEvent::Finished {
+                    index,
+                    stdout,
+                    stderr,
+                    exit_code,
+                    duration,
+                } => TaskEvent::Finished {
+                    index,
+                    stdout,
+                    stderr,
+                    duration,
+                    exit_code: if ok_codes.contains(&exit_code) {
+                        0
+                    } else {
+                        exit_code
+                    },
+                },
+                event => event,
+            }
+        }
+        Operation::Clone { vcs, spec, dest } => {
+            let _permits = acquire(sem, host_sem).await;
+            let _ = tx.send(TaskEvent::Started { index: i });
+            let started = Instant::now();
+
+            let parent = dest.parent().unwrap_or(&dest);
+            let _ = tokio::fs::create_dir_all(parent).await;
+
+            let result = Command::new(vcs.program())
+                .args(vcs.clone_args(&spec, &dest))
+                .env("GIT_TERMINAL_PROMPT", "0")
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .output()
+                .await;
+            finished(i, result, "", started)
+        }
+        Operation::Shell { cmd, work_dir } => {
+            let _permits = acquire(sem, host_sem).await;
+            let _ = tx.send(TaskEvent::Started { index: i });
+            let started = Instant::now();
+            let result = Command::new("sh")
+                .args(["-c", &cmd])
+                .current_dir(&work_dir)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .output()
+                .await;
+            finished(i, result, "", started)
+        }
     }
+}
 
-    rx
+/// `Finished` event for a process result; `program` names it in spawn errors
+fn finished(
+    i: usize,
+    result: std::io::Result<Output>,
+    program: &str,
+    started: Instant,
+) -> TaskEvent {
+    let duration = started.elapsed();
+    match result {
+        Ok(output) => TaskEvent::Finished {
+            index: i,
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            exit_code: output.status.code().unwrap_or(1),
+            duration,
+        },
+        Err(e) if program.is_empty() => TaskEvent::Finished {
+            index: i,
+            stdout: String::new(),
+            stderr: format!("failed to execute: {}", e),
+            exit_code: 1,
+            duration,
+        },
+        Err(e) => TaskEvent::Finished {
+            index: i,
+            stdout: String::new(),
+            stderr: format!("failed to execute {}: {}", program, e),
+            exit_code: 1,
+            duration,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::Vcs;
+    use std::path::PathBuf;
+
+    fn repo(section: &str, depends: &[&str]) -> Repo {
+        Repo {
+            name: section.to_string(),
+            section: section.to_string(),
+            path: PathBuf::from("/"),
+            clone: None,
+            submodules: false,
+            vcs: Vcs::Git,
+            depends: depends.iter().map(|d| d.to_string()).collect(),
+            group: None,
+        }
+    }
+
+    fn shell(cmd: &str) -> Operation {
+        Operation::Shell {
+            cmd: cmd.into(),
+            work_dir: PathBuf::from("/"),
+        }
+    }
+
+    /// Final event of each repo: `Ok(exit code)` or `Err(skip reason)`
+    async fn outcomes(repos: &[Repo], ops: Vec<Operation>) -> Vec<Result<i32, String>> {
+        let mut rx = execute_all(repos, ops, &Limits::new(4, &Settings::default()));
+        let mut outcomes = vec![Err("no result".to_string()); repos.len()];
+        while let Some(event) = rx.recv().await {
+            match event {
+                TaskEvent::Finished {
+                    index, exit_code, ..
+                } => outcomes[index] = Ok(exit_code),
+                TaskEvent::Skipped { index, reason } => outcomes[index] = Err(reason),
+                _ => {}
+            }
+        }
+        outcomes
+    }
+
+    #[tokio::test]
+    async fn test_remote_host_from_url_or_origin() {
+        let tmp = crate::testutil::TempDir::new("remote-host");
+        assert_eq!(
+            remote_host(Some("git@github.com:org/a.git"), Vcs::Hg, tmp.path())
+                .await
+                .as_deref(),
+            Some("github.com")
+        );
+        crate::testutil::init_repo(tmp.path());
+        assert_eq!(remote_host(None, Vcs::Git, tmp.path()).await, None);
+        crate::testutil::git(
+            tmp.path(),
+            &["remote", "add", "origin", "https://GitLab.example.com/a"],
+        );
+        assert_eq!(
+            remote_host(None, Vcs::Git, tmp.path()).await.as_deref(),
+            Some("gitlab.example.com")
+        );
+        // git isn't asked about the remotes of other VCSs' repos
+        assert_eq!(remote_host(None, Vcs::Hg, tmp.path()).await, None);
+        assert_eq!(remote_host(None, Vcs::Plain, tmp.path()).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_dependents_wait_and_skip_on_failure() {
+        let repos = vec![
+            repo("ok", &[]),
+            repo("fails", &[]),
+            repo("skipped", &[]),
+            repo("after-ok", &["ok"]),
+            repo("after-fails", &["fails"]),
+            repo("after-skipped", &["skipped"]),
+            repo("after-after-fails", &["after-fails"]),
+        ];
+        let ops = vec![
+            shell("sleep 0.1"),
+            shell("exit 3"),
+            Operation::NotCheckedOut,
+            shell("true"),
+            shell("true"),
+            shell("true"),
+            shell("true"),
+        ];
+        assert_eq!(
+            outcomes(&repos, ops).await,
+            vec![
+                Ok(0),
+                Ok(3),
+                Err("not checked out".into()),
+                Ok(0),
+                Err("dependency fails didn't succeed".into()),
+                Err("dependency skipped didn't succeed".into()),
+                Err("dependency after-fails didn't succeed".into()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dependent_starts_after_dependency_finishes() {
+        let tmp = crate::testutil::TempDir::new("depends");
+        let marker = tmp.path().join("marker");
+        let repos = vec![repo("first", &[]), repo("second", &["first"])];
+        let ops = vec![
+            shell(&format!("sleep 0.2 && touch '{}'", marker.display())),
+            shell(&format!("test -e '{}'", marker.display())),
+        ];
+        assert_eq!(outcomes(&repos, ops).await, vec![Ok(0), Ok(0)]);
+    }
+
+    #[tokio::test]
+    async fn test_runs_share_limits() {
+        let tmp = crate::testutil::TempDir::new("limits");
+        // Fails if another task holds the lock at the same time
+        let lock = format!(
+            "mkdir '{0}' && sleep 0.1 && rmdir '{0}'",
+            tmp.path().join("lock").display()
+        );
+        let limits = Limits::new(1, &Settings::default());
+        let repos = vec![repo("a", &[])];
+        let mut first = execute_all(&repos, vec![shell(&lock)], &limits);
+        let mut second = execute_all(&repos, vec![shell(&lock)], &limits);
+        let mut exit_codes = Vec::new();
+        for rx in [&mut first, &mut second] {
+            while let Some(event) = rx.recv().await {
+                if let TaskEvent::Finished { exit_code, .. } = event {
+                    exit_codes.push(exit_code);
+                }
+            }
+        }
+        assert_eq!(exit_codes, vec![0, 0]);
+    }
 }
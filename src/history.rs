@@ -0,0 +1,591 @@
+use crate::cli;
+use crate::config::Repo;
+use crate::shellwords;
+use crate::tui::state::{AppState, RepoStatus};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How many runs to keep; older ones are deleted when a new run is recorded
+const MAX_RUNS: usize = 100;
+
+/// A recorded run: one file per run, named after its start time in ms
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub id: u64,
+    /// Display name of the command, e.g. `run`
+    pub command: String,
+    /// Full command line, e.g. `run 'make test'`. Empty in runs recorded
+    /// before it was stored.
+    pub args: Vec<String>,
+    pub repos: Vec<RunRepo>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunRepo {
+    pub name: String,
+    pub section: String,
+    pub path: PathBuf,
+    pub status: RepoStatus,
+    pub duration: Option<Duration>,
+}
+
+/// Outcome of one repo in a run, for comparing runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Ok,
+    Failed,
+    Skipped,
+    /// Not part of the run
+    Absent,
+}
+
+impl Outcome {
+    fn of(status: &RepoStatus) -> Outcome {
+        match status {
+            RepoStatus::Done { exit_code: 0, .. } => Outcome::Ok,
+            RepoStatus::Done { .. } => Outcome::Failed,
+            _ => Outcome::Skipped,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Outcome::Ok => "ok",
+            Outcome::Failed => "failed",
+            Outcome::Skipped => "skipped",
+            Outcome::Absent => "-",
+        }
+    }
+}
+
+/// Directory holding recorded runs: `$MRX_HISTORY_DIR`, or `mrx/history`
+/// under the user's data dir.
+pub fn history_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("MRX_HISTORY_DIR") {
+        return PathBuf::from(dir);
+    }
+    dirs::data_dir()
+        .expect("cannot determine data directory")
+        .join("mrx")
+        .join("history")
+}
+
+impl Run {
+    /// Snapshot a finished (or interrupted) TUI session
+    pub fn from_state(state: &AppState) -> Run {
+        let id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let repos = state
+            .repos
+            .iter()
+            .zip(&state.statuses)
+            .zip(&state.durations)
+            .map(|((repo, status), duration)| RunRepo {
+                name: repo.name.clone(),
+                section: repo.section.clone(),
+                path: repo.path.clone(),
+                status: match status {
+                    RepoStatus::Done { .. } | RepoStatus::Skipped { .. } => status.clone(),
+                    _ => RepoStatus::Skipped {
+                        reason: "interrupted".into(),
+                    },
+                },
+                duration: *duration,
+            })
+            .collect();
+        Run {
+            id,
+            command: state.command_name.clone(),
+            args: state.command_args.clone(),
+            repos,
+        }
+    }
+
+    /// TUI state showing this run's results
+    pub fn into_state(self) -> AppState {
+        let subtitle = format!("run of {}", format_time(self.id / 1000));
+        let repos: Vec<Repo> = self
+            .repos
+            .iter()
+            .map(|r| Repo {
+                name: r.name.clone(),
+                section: r.section.clone(),
+                path: r.path.clone(),
+                clone: None,
+                submodules: false,
+                vcs: Default::default(),
+                depends: Vec::new(),
+                group: None,
+            })
+            .collect();
+        let mut state = AppState::new(repos, &self.command);
+        state.command_args = self.args;
+        state.subtitle = Some(subtitle);
+        state.durations = self.repos.iter().map(|r| r.duration).collect();
+        state.statuses = self.repos.into_iter().map(|r| r.status).collect();
+        state
+    }
+
+    /// The command as typed, to tell runs of `run` apart
+    pub fn command_line(&self) -> String {
+        if self.args.is_empty() {
+            self.command.clone()
+        } else {
+            shellwords::join(&self.args)
+        }
+    }
+
+    /// The subcommand and its own arguments, without global options such
+    /// as `-j`, to find earlier runs of the same command
+    fn command_key(&self) -> Vec<String> {
+        if self.args.is_empty() {
+            vec![self.command.clone()]
+        } else {
+            cli::command_args(&self.args)
+        }
+    }
+
+    pub fn counts(&self) -> String {
+        let count = |o: Outcome| {
+            self.repos
+                .iter()
+                .filter(|r| Outcome::of(&r.status) == o)
+                .count()
+        };
+        let mut parts = vec![format!("{} ok", count(Outcome::Ok))];
+        for (n, label) in [
+            (count(Outcome::Failed), "failed"),
+            (count(Outcome::Skipped), "skipped"),
+        ] {
+            if n > 0 {
+                parts.push(format!("{} {}", n, label));
+            }
+        }
+        parts.join(", ")
+    }
+
+    fn serialize(&self) -> String {
+        let mut out = format!("mrx-run 1\ncommand {}\n", self.command);
+        if !self.args.is_empty() {
+            out.push_str(&format!(
+                "args {}\n",
+                one_line(&shellwords::join(&self.args))
+            ));
+        }
+        for repo in &self.repos {
+            out.push_str(&format!("repo {}\n", repo.name));
+            out.push_str(&format!("section {}\n", repo.section));
+            out.push_str(&format!("path {}\n", repo.path.display()));
+            if let Some(d) = repo.duration {
+                out.push_str(&format!("duration_ms {}\n", d.as_millis()));
+            }
+            match &repo.status {
+                RepoStatus::Done {
+                    summary,
+                    changed,
+                    stdout,
+                    stderr,
+                    exit_code,
+                } => {
+                    out.push_str(&format!("exit {}\n", exit_code));
+                    if !changed {
+                        out.push_str("quiet\n");
+                    }
+                    out.push_str(&format!("summary {}\n", one_line(summary)));
+                    // Output can contain anything, so it's length-prefixed
+                    out.push_str(&format!("stdout {}\n{}\n", stdout.len(), stdout));
+                    out.push_str(&format!("stderr {}\n{}\n", stderr.len(), stderr));
+                }
+                status => {
+                    let reason = match status {
+                        RepoStatus::Skipped { reason } => reason.as_str(),
+                        _ => "interrupted",
+                    };
+                    out.push_str(&format!("skipped {}\n", one_line(reason)));
+                }
+            }
+        }
+        out
+    }
+
+    fn parse(id: u64, content: &str) -> Result<Run, String> {
+        fn next_line(rest: &mut &str) -> Option<String> {
+            if rest.is_empty() {
+                return None;
+            }
+            let (line, tail) = rest.split_once('\n').unwrap_or((rest, ""));
+            *rest = tail;
+            Some(line.to_string())
+        }
+        fn take(rest: &mut &str, len: usize) -> Result<String, String> {
+            let text = rest.get(..len).ok_or("truncated output")?.to_string();
+            *rest = rest[len..].strip_prefix('\n').unwrap_or(&rest[len..]);
+            Ok(text)
+        }
+
+        let mut rest = content;
+
+        if next_line(&mut rest).as_deref() != Some("mrx-run 1") {
+            return Err("not a run file".into());
+        }
+        let mut run = Run {
+            id,
+            command: String::new(),
+            args: Vec::new(),
+            repos: Vec::new(),
+        };
+        let mut summary = String::new();
+        let mut quiet = false;
+        let mut stdout = String::new();
+        let mut exit_code = 0;
+
+        while let Some(line) = next_line(&mut rest) {
+            let (key, value) = line.split_once(' ').unwrap_or((&line, ""));
+            let repo = run.repos.last_mut();
+            match (key, repo) {
+                ("command", _) => run.command = value.to_string(),
+                ("args", _) => run.args = shellwords::split_commands(value)?.concat(),
+                ("repo", _) => run.repos.push(RunRepo {
+                    name: value.to_string(),
+                    section: String::new(),
+                    path: PathBuf::new(),
+                    status: RepoStatus::Pending,
+                    duration: None,
+                }),
+                ("section", Some(r)) => r.section = value.to_string(),
+                ("path", Some(r)) => r.path = PathBuf::from(value),
+                ("duration_ms", Some(r)) => {
+                    r.duration = value.parse().ok().map(Duration::from_millis)
+                }
+                ("exit", Some(_)) => exit_code = value.parse().map_err(|_| "bad exit code")?,
+                ("quiet", Some(_)) => quiet = true,
+                ("summary", Some(_)) => summary = value.to_string(),
+                ("stdout", Some(_)) => {
+                    stdout = take(&mut rest, value.parse().map_err(|_| "bad length")?)?
+                }
+                ("stderr", Some(r)) => {
+                    let stderr = take(&mut rest, value.parse().map_err(|_| "bad length")?)?;
+                    r.status = RepoStatus::Done {
+                        summary: std::mem::take(&mut summary),
+                        changed: !std::mem::take(&mut quiet),
+                        stdout: std::mem::take(&mut stdout),
+                        stderr,
+                        exit_code,
+                    };
+                }
+                ("skipped", Some(r)) => {
+                    r.status = RepoStatus::Skipped {
+                        reason: value.to_string(),
+                    }
+                }
+                _ => return Err(format!("unexpected line: {}", line)),
+            }
+        }
+        Ok(run)
+    }
+}
+
+fn one_line(s: &str) -> String {
+    s.replace(['\n', '\r'], " ")
+}
+
+fn run_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{}.run", id))
+}
+
+/// Save a run and drop the oldest ones beyond `MAX_RUNS`
+pub fn record(run: &Run) -> io::Result<()> {
+    let dir = history_dir();
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(run_path(&dir, run.id), run.serialize())?;
+    for old in list_ids().into_iter().skip(MAX_RUNS) {
+        let _ = std::fs::remove_file(run_path(&dir, old));
+    }
+    Ok(())
+}
+
+/// Ids of recorded runs, newest first
+pub fn list_ids() -> Vec<u64> {
+    let Ok(entries) = std::fs::read_dir(history_dir()) else {
+        return Vec::new();
+    };
+    let mut ids: Vec<u64> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            name.strip_suffix(".run")?.parse().ok()
+        })
+        .collect();
+    ids.sort_unstable_by(|a, b| b.cmp(a));
+    ids
+}
+
+pub fn load(id: u64) -> Result<Run, String> {
+    let path = run_path(&history_dir(), id);
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    Run::parse(id, &content).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Load the run numbered `n` in `mrx history` (1 = most recent)
+fn load_nth(ids: &[u64], n: usize) -> Run {
+    let Some(&id) = n.checked_sub(1).and_then(|i| ids.get(i)) else {
+        eprintln!("error: no run #{} ({} recorded)", n, ids.len());
+        std::process::exit(1);
+    };
+    load(id).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// The most recent run, for `mrx last`
+pub fn last() -> Run {
+    let ids = list_ids();
+    if ids.is_empty() {
+        eprintln!("error: no runs recorded yet");
+        std::process::exit(1);
+    }
+    load_nth(&ids, 1)
+}
+
+/// Print recorded runs, newest first, numbered for `history diff`
+pub fn print_list() {
+    for (i, id) in list_ids().into_iter().enumerate() {
+        match load(id) {
+            Ok(run) => println!(
+                "{:>3}  {}  {:<12} {}",
+                i + 1,
+                format_time(id / 1000),
+                run.command_line(),
+                run.counts()
+            ),
+            Err(e) => eprintln!("warning: {}", e),
+        }
+    }
+}
+
+/// Repos whose outcome differs between two runs, by section. Names can
+/// change as repos are added, sections don't.
+pub fn diff(old: &Run, new: &Run) -> Vec<(String, Outcome, Outcome)> {
+    let mut outcomes: BTreeMap<&str, (Outcome, Outcome)> = BTreeMap::new();
+    for r in &old.repos {
+        outcomes
+            .entry(&r.section)
+            .or_insert((Outcome::Absent, Outcome::Absent))
+            .0 = Outcome::of(&r.status);
+    }
+    for r in &new.repos {
+        outcomes
+            .entry(&r.section)
+            .or_insert((Outcome::Absent, Outcome::Absent))
+            .1 = Outcome::of(&r.status);
+    }
+    outcomes
+        .into_iter()
+        .filter(|(_, (a, b))| a != b)
+        .map(|(name, (a, b))| (name.to_string(), a, b))
+        .collect()
+}
+
+/// `mrx history diff`: compare run `new` (default: latest) with run `old`
+/// (default: the previous run of the same command)
+pub fn print_diff(old: Option<usize>, new: Option<usize>) {
+    let ids = list_ids();
+    let new_n = new.unwrap_or(1);
+    let new_run = load_nth(&ids, new_n);
+    let old_n = match old {
+        Some(n) => n,
+        None => (new_n + 1..=ids.len())
+            .find(|&n| load(ids[n - 1]).is_ok_and(|r| r.command_key() == new_run.command_key()))
+            .unwrap_or_else(|| {
+                eprintln!(
+                    "error: no earlier `{}` run to compare with",
+                    new_run.command_line()
+                );
+                std::process::exit(1);
+            }),
+    };
+    let old_run = load_nth(&ids, old_n);
+
+    println!(
+        "#{} {} {}  ->  #{} {} {}",
+        old_n,
+        old_run.command_line(),
+        format_time(old_run.id / 1000),
+        new_n,
+        new_run.command_line(),
+        format_time(new_run.id / 1000)
+    );
+    let changes = diff(&old_run, &new_run);
+    if changes.is_empty() {
+        println!("no status changes");
+        return;
+    }
+    let width = changes
+        .iter()
+        .map(|(section, ..)| section.len())
+        .max()
+        .unwrap_or(0);
+    for (section, from, to) in &changes {
+        let detail = new_run
+            .repos
+            .iter()
+            .find(|r| r.section == *section)
+            .map(|r| match &r.status {
+                RepoStatus::Done { summary, .. } => summary.clone(),
+                RepoStatus::Skipped { reason } => reason.clone(),
+                _ => String::new(),
+            })
+            .unwrap_or_default();
+        println!(
+            "  {:width$}  {:>7} -> {:<7}  {}",
+            section,
+            from.name(),
+            to.name(),
+            detail,
+            width = width
+        );
+    }
+}
+
+/// `YYYY-MM-DD HH:MM` in UTC
+pub fn format_time(unix_secs: u64) -> String {
+    let days = (unix_secs / 86400) as i64;
+    let secs = unix_secs % 86400;
+    // Civil-from-days (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(statuses: &[(&str, RepoStatus)]) -> Run {
+        Run {
+            id: 1_700_000_000_000,
+            command: "run".into(),
+            args: vec!["-j".into(), "4".into(), "run".into(), "make test".into()],
+            repos: statuses
+                .iter()
+                .map(|(name, status)| RunRepo {
+                    name: name.to_string(),
+                    section: format!("src/{}", name),
+                    path: PathBuf::from(format!("/work/src/{}", name)),
+                    status: status.clone(),
+                    duration: Some(Duration::from_millis(1234)),
+                })
+                .collect(),
+        }
+    }
+
+    fn done(exit_code: i32, stdout: &str) -> RepoStatus {
+        RepoStatus::Done {
+            summary: "summary".into(),
+            changed: exit_code != 0 || !stdout.is_empty(),
+            stdout: stdout.into(),
+            stderr: String::new(),
+            exit_code,
+        }
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let original = run(&[
+            ("api", done(0, "line 1\nstdout 99\n\nrepo fake\n")),
+            ("web", done(1, "")),
+            ("lib", done(0, "")),
+            (
+                "docs",
+                RepoStatus::Skipped {
+                    reason: "not checked out".into(),
+                },
+            ),
+        ]);
+        let parsed = Run::parse(original.id, &original.serialize()).unwrap();
+        assert_eq!(parsed, original);
+        assert_eq!(parsed.command_line(), "-j 4 run 'make test'");
+    }
+
+    #[test]
+    fn test_command_line_of_older_runs() {
+        let mut old = run(&[]);
+        old.args.clear();
+        let serialized = old.serialize();
+        assert!(!serialized.contains("\nargs "));
+        let parsed = Run::parse(old.id, &serialized).unwrap();
+        assert_eq!(parsed.command_line(), "run");
+    }
+
+    #[test]
+    fn test_diff_reports_changed_outcomes() {
+        let old = run(&[
+            ("api", done(0, "")),
+            ("web", done(0, "")),
+            ("old", done(0, "")),
+        ]);
+        let new = run(&[
+            ("api", done(0, "")),
+            ("web", done(1, "")),
+            ("new", done(0, "")),
+        ]);
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                ("src/new".into(), Outcome::Absent, Outcome::Ok),
+                ("src/old".into(), Outcome::Ok, Outcome::Absent),
+                ("src/web".into(), Outcome::Ok, Outcome::Failed),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_matches_renamed_repos_by_section() {
+        let old = run(&[("api", done(0, ""))]);
+        let mut new = old.clone();
+        new.repos[0].name = "team/api".into();
+        assert_eq!(diff(&old, &new), vec![]);
+    }
+
+    #[test]
+    fn test_command_key_ignores_global_options() {
+        let mut old = run(&[]);
+        old.args = vec!["status".into()];
+        let mut new = run(&[]);
+        new.args = vec![
+            "-j".into(),
+            "4".into(),
+            "status".into(),
+            "--exit-when-done".into(),
+        ];
+        assert_eq!(old.command_key(), new.command_key());
+        new.args = vec!["diff".into()];
+        assert_ne!(old.command_key(), new.command_key());
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "1970-01-01 00:00");
+        assert_eq!(format_time(1_700_000_000), "2023-11-14 22:13");
+    }
+}
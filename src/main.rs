@@ -1,14 +1,23 @@
 mod cli;
 mod config;
+mod config_writer;
+mod doctor;
 mod executor;
+mod history;
+mod mirror;
 mod operations;
+mod register;
+mod shellwords;
 mod summarize;
+#[cfg(test)]
+mod testutil;
 mod tui;
+mod vcs;
+mod worktree;
 
 use clap::Parser;
-use cli::Cli;
-use std::path::PathBuf;
-use std::process::Command as StdCommand;
+use cli::{Cli, Command, ConfigAction, HistoryAction, WorktreeAction};
+use std::path::{Path, PathBuf};
 
 fn resolve_config_path(cli: &Cli) -> PathBuf {
     if let Some(ref p) = cli.config {
@@ -20,7 +29,7 @@ fn resolve_config_path(cli: &Cli) -> PathBuf {
     }
 }
 
-fn resolve_base_dir(cli: &Cli, config_path: &PathBuf) -> PathBuf {
+fn resolve_base_dir(cli: &Cli, config_path: &Path) -> PathBuf {
     if let Some(ref d) = cli.directory {
         d.clone()
     } else {
@@ -41,24 +50,91 @@ async fn main() {
 
     let config_path = resolve_config_path(&cli);
     let base_dir = resolve_base_dir(&cli, &config_path);
-    let repos = config::parse_config(&config_path, &base_dir);
-
-    // Register command: add current dir to config
-    if cli.command.is_register() {
-        register(&config_path, &base_dir);
+    let mut repos = config::parse_config(&config_path, &base_dir);
+
+    // Config editing commands: register, discover, unregister, config set
+    if cli.command.is_config_edit() {
+        match &cli.command {
+            Command::Register { paths, remote } => {
+                register::register(&config_path, &base_dir, paths, remote)
+            }
+            Command::Discover { dir, remote, yes } => {
+                register::discover(&config_path, &base_dir, dir.as_deref(), remote, *yes)
+            }
+            Command::Unregister { path } => {
+                register::unregister(&config_path, &base_dir, path.as_deref())
+            }
+            Command::Config {
+                action: ConfigAction::Set { repo, key, value },
+            } => register::config_set(&config_path, &repos, repo, key, value),
+            _ => unreachable!(),
+        }
         return;
     }
 
     // List command: just print and exit
-    if cli.command.is_list() {
-        for repo in &repos {
-            let exists = repo.path.is_dir();
-            let marker = if exists { "✓" } else { "-" };
-            println!("{} {:24} {}", marker, repo.name, repo.path.display());
+    if let Command::List { json } | Command::Ls { json } = cli.command {
+        list(&repos, json);
+        return;
+    }
+
+    // History commands read recorded runs instead of running anything
+    match &cli.command {
+        Command::Last => {
+            let settings = config::parse_settings(&config_path);
+            tui::replay(history::last().into_state(), &settings).expect("TUI error");
+            return;
         }
+        Command::History { action: None } => {
+            history::print_list();
+            return;
+        }
+        Command::History {
+            action: Some(HistoryAction::Diff { old, new }),
+        } => {
+            history::print_diff(*old, *new);
+            return;
+        }
+        _ => {}
+    }
+
+    // Worktrees command: print each repo's worktrees
+    if let Command::Worktrees = cli.command {
+        worktree::print_all(&repos).await;
         return;
     }
 
+    // Doctor command: cross-check config and filesystem
+    if cli.command.is_doctor() {
+        let ok = doctor::run(&repos, &base_dir);
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    if let Err(e) = config::check_dependencies(&repos) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+
+    // Worktree add only touches the repos it names
+    if let Command::Worktree {
+        action: WorktreeAction::Add { repos: names, .. },
+    } = &cli.command
+    {
+        if !names.is_empty() {
+            for name in names {
+                if !repos.iter().any(|r| &r.name == name || &r.section == name) {
+                    eprintln!("error: no repo named {}", name);
+                    std::process::exit(1);
+                }
+            }
+            repos.retain(|r| names.contains(&r.name) || names.contains(&r.section));
+        }
+    }
+
+    if cli.worktrees {
+        repos = worktree::expand(repos);
+    }
+
     // Plan operations
     let ops: Vec<operations::Operation> = repos
         .iter()
@@ -67,77 +143,84 @@ async fn main() {
 
     // Execute
     let jobs = max_jobs(&cli);
-    let rx = executor::execute_all(&repos, ops, jobs);
+    let settings = config::parse_settings(&config_path);
+    let limits = executor::Limits::new(jobs, &settings);
+    let rx = executor::execute_all(&repos, ops, &limits);
 
     // Run TUI
-    let success = tui::run(repos, &cli.command, rx).expect("TUI error");
+    let options = tui::Options {
+        limits,
+        exit_when_done: cli.exit_when_done,
+        report_changed: cli.report_changed,
+        watch: cli.watch,
+        args: std::env::args().skip(1).collect(),
+    };
+    let state = tui::run(repos, &cli.command, rx, &options, &settings).expect("TUI error");
+    if let Err(e) = history::record(&history::Run::from_state(&state)) {
+        eprintln!("warning: cannot record run history: {}", e);
+    }
 
-    std::process::exit(if success { 0 } else { 1 });
+    std::process::exit(if state.failed_count() == 0 { 0 } else { 1 });
 }
 
-fn register(config_path: &PathBuf, base_dir: &PathBuf) {
-    let cwd = std::env::current_dir().expect("cannot determine current directory");
-
-    // Get the remote URL
-    let output = StdCommand::new("git")
-        .args(["remote", "get-url", "origin"])
-        .current_dir(&cwd)
-        .output();
-
-    let url = match output {
-        Ok(o) if o.status.success() => String::from_utf8_lossy(&o.stdout).trim().to_string(),
-        _ => {
-            eprintln!("error: not a git repo or no 'origin' remote");
-            std::process::exit(1);
-        }
-    };
-
-    let repo_name = cwd
-        .file_name()
-        .expect("cannot determine directory name")
-        .to_string_lossy();
-
-    // Compute relative section path from base_dir
-    let section = match cwd.strip_prefix(base_dir) {
-        Ok(rel) => rel.to_string_lossy().to_string(),
-        Err(_) => {
-            eprintln!(
-                "error: {} is not under base dir {}",
-                cwd.display(),
-                base_dir.display()
-            );
-            std::process::exit(1);
+fn list(repos: &[config::Repo], json: bool) {
+    if json {
+        let entries: Vec<String> = repos
+            .iter()
+            .map(|r| {
+                format!(
+                    "  {{\"name\": {}, \"section\": {}, \"path\": {}, \"clone_url\": {}, \"checked_out\": {}}}",
+                    json_string(&r.name),
+                    json_string(&r.section),
+                    json_string(&r.path.to_string_lossy()),
+                    r.clone_url()
+                        .map(json_string)
+                        .unwrap_or_else(|| "null".into()),
+                    r.path.is_dir()
+                )
+            })
+            .collect();
+        if entries.is_empty() {
+            println!("[]");
+        } else {
+            println!("[\n{}\n]", entries.join(",\n"));
         }
-    };
-
-    // Check if already registered
-    let existing = std::fs::read_to_string(config_path).unwrap_or_default();
-    let section_header = format!("[{}]", section);
-    if existing.contains(&section_header) {
-        eprintln!("already registered: {}", section);
         return;
     }
 
-    // Append to config
-    let entry = format!(
-        "\n[{}]\ncheckout = git clone '{}' '{}'\n",
-        section, url, repo_name
-    );
-
-    use std::io::Write;
-    let mut file = std::fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(config_path)
-        .unwrap_or_else(|e| {
-            eprintln!("error: cannot write {}: {}", config_path.display(), e);
-            std::process::exit(1);
-        });
-
-    file.write_all(entry.as_bytes()).unwrap_or_else(|e| {
-        eprintln!("error: write failed: {}", e);
-        std::process::exit(1);
-    });
+    let width = repos
+        .iter()
+        .map(|r| r.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(24);
+    for repo in repos {
+        let exists = repo.path.is_dir();
+        let marker = if exists { "✓" } else { "-" };
+        println!(
+            "{} {:width$} {}",
+            marker,
+            repo.name,
+            repo.path.display(),
+            width = width
+        );
+    }
+}
 
-    println!("registered {} ({})", section, url);
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
 }
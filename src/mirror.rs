@@ -0,0 +1,196 @@
+use crate::config::{CloneSpec, Repo};
+use crate::operations::Operation;
+use crate::vcs::Vcs;
+use std::path::{Path, PathBuf};
+
+/// Directory holding the bare mirrors: `$MRX_MIRROR_DIR`, or
+/// `mrx/mirrors` under the user's cache dir.
+pub fn cache_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("MRX_MIRROR_DIR") {
+        return PathBuf::from(dir);
+    }
+    dirs::cache_dir()
+        .expect("cannot determine cache directory")
+        .join("mrx")
+        .join("mirrors")
+}
+
+/// Where the mirror of `url` lives under `dir`. Mirrors are keyed by URL
+/// rather than section, so workspaces with different layouts share them.
+pub fn mirror_path(dir: &Path, url: &str) -> PathBuf {
+    let mut rest = url.trim();
+    if let Some((_, after)) = rest.split_once("://") {
+        rest = after;
+    }
+    // Drop `user@` from `user@host:path` and `user@host/path`
+    if let Some(at) = rest.find('@') {
+        if !rest[..at].contains('/') {
+            rest = &rest[at + 1..];
+        }
+    }
+    let rest = rest.trim_end_matches('/');
+    let rest = rest.strip_suffix(".git").unwrap_or(rest);
+
+    let parts: Vec<&str> = rest
+        .split(['/', ':'])
+        .filter(|p| !p.is_empty() && *p != "." && *p != "..")
+        .collect();
+    let mut path = dir.join(parts.join("/"));
+    path.as_mut_os_string().push(".git");
+    path
+}
+
+/// Existing mirror for a repo, if there is one
+pub fn find(repo: &Repo) -> Option<PathBuf> {
+    let path = mirror_path(&cache_dir(), repo.clone_url()?);
+    path.is_dir().then_some(path)
+}
+
+/// Create the repo's mirror under `dir`, or fetch into it if it already
+/// exists
+pub fn plan_update(dir: &Path, repo: &Repo) -> Operation {
+    let url = match repo.clone_url() {
+        Some(url) => url,
+        None => {
+            return Operation::Skip {
+                reason: "no clone URL".into(),
+            }
+        }
+    };
+
+    let path = mirror_path(dir, url);
+    if path.is_dir() {
+        Operation::Git {
+            args: vec!["fetch".into(), "--prune".into()],
+            work_dir: path,
+        }
+    } else {
+        Operation::Clone {
+            vcs: Vcs::Git,
+            spec: CloneSpec {
+                url: url.to_string(),
+                extra_args: vec!["--mirror".into()],
+                ..Default::default()
+            },
+            dest: path,
+        }
+    }
+}
+
+/// Namespace the mirror's branches are fetched into. Remote-tracking refs
+/// are left to the real fetch, so a mirror that is behind can't rewind them
+/// and repos whose remote isn't `origin` work the same way.
+const PREFETCH_NAMESPACE: &str = "refs/mrx/mirror";
+
+/// Git arguments to copy a mirror's objects into a checkout, so the
+/// following fetch from the real remote only transfers new objects.
+pub fn prefetch_args(mirror: &Path) -> Vec<String> {
+    vec![
+        "fetch".into(),
+        "--quiet".into(),
+        "--no-tags".into(),
+        mirror.to_string_lossy().to_string(),
+        format!("+refs/heads/*:{}/*", PREFETCH_NAMESPACE),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{git, init_repo, TempDir};
+
+    /// Run a planned git operation synchronously
+    fn run(op: Operation) {
+        let (dir, args) = match op {
+            Operation::Clone { vcs, spec, dest } => {
+                (PathBuf::from("/"), vcs.clone_args(&spec, &dest))
+            }
+            Operation::Git { args, work_dir } => (work_dir, args),
+            other => panic!("unexpected operation: {:?}", other),
+        };
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        git(&dir, &args);
+    }
+
+    #[test]
+    fn test_mirror_path_shared_across_url_styles() {
+        let dir = Path::new("/cache");
+        let expected = PathBuf::from("/cache/github.com/org/repo.git");
+        assert_eq!(mirror_path(dir, "git@github.com:org/repo.git"), expected);
+        assert_eq!(mirror_path(dir, "https://github.com/org/repo"), expected);
+        assert_eq!(
+            mirror_path(dir, "ssh://git@github.com/org/repo.git/"),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_mirror_path_stays_inside_dir() {
+        assert_eq!(
+            mirror_path(Path::new("/cache"), "file:///srv/../git/a.b"),
+            PathBuf::from("/cache/srv/git/a.b.git")
+        );
+    }
+
+    #[test]
+    fn test_mirror_update_reference_clone_and_fetch() {
+        let tmp = TempDir::new("mirror");
+        let origin = tmp.path().join("origin");
+        init_repo(&origin);
+        let url = format!("file://{}", origin.display());
+        let cache = tmp.path().join("cache");
+        let repo = Repo {
+            name: "app".into(),
+            section: "app".into(),
+            path: tmp.path().join("work/app"),
+            clone: Some(CloneSpec {
+                url: url.clone(),
+                ..Default::default()
+            }),
+            submodules: false,
+            vcs: Vcs::Git,
+            depends: Vec::new(),
+            group: None,
+        };
+
+        // First update creates the mirror, the next one fetches into it
+        let mirror = mirror_path(&cache, &url);
+        run(plan_update(&cache, &repo));
+        assert!(mirror.is_dir());
+        git(&origin, &["commit", "-q", "--allow-empty", "-m", "second"]);
+        let op = plan_update(&cache, &repo);
+        assert!(matches!(&op, Operation::Git { args, .. } if args[0] == "fetch"));
+        run(op);
+        let head = git(&origin, &["rev-parse", "HEAD"]);
+        assert_eq!(git(&mirror, &["rev-parse", "main"]), head);
+
+        // Clone borrowing objects from the mirror
+        let spec = CloneSpec {
+            reference: Some(mirror.clone()),
+            ..repo.clone.clone().unwrap()
+        };
+        run(Operation::Clone {
+            vcs: Vcs::Git,
+            spec,
+            dest: repo.path.clone(),
+        });
+        assert!(repo.path.join(".git/objects/info/alternates").exists());
+
+        // Prefetch from the mirror, then fetch from the real remote. The
+        // mirror is behind, and must not rewind origin's tracking branch.
+        git(&origin, &["commit", "-q", "--allow-empty", "-m", "third"]);
+        git(&repo.path, &["fetch", "-q"]);
+        let new_head = git(&origin, &["rev-parse", "HEAD"]);
+        run(Operation::Git {
+            args: prefetch_args(&mirror),
+            work_dir: repo.path.clone(),
+        });
+        assert_eq!(git(&repo.path, &["rev-parse", "origin/main"]), new_head);
+        assert_eq!(
+            git(&repo.path, &["rev-parse", "refs/mrx/mirror/main"]),
+            head
+        );
+        git(&repo.path, &["fetch", "-q"]);
+        assert_eq!(git(&repo.path, &["rev-parse", "origin/main"]), new_head);
+    }
+}
@@ -1,5 +1,8 @@
-use crate::cli::Command;
-use crate::config::Repo;
+use crate::cli::{CloneOpts, Command, FetchOpts, MirrorAction, WorktreeAction};
+use crate::config::{CloneSpec, Repo};
+use crate::mirror;
+use crate::vcs::{self, Vcs};
+use crate::worktree;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
@@ -9,8 +12,26 @@ pub enum Operation {
         args: Vec<String>,
         work_dir: PathBuf,
     },
+    /// Run several git commands in the repo directory, stopping at the
+    /// first failure
+    GitSeq {
+        commands: Vec<Vec<String>>,
+        work_dir: PathBuf,
+    },
+    /// Run a command of another VCS (hg, jj) in the repo directory
+    Exec {
+        program: &'static str,
+        args: Vec<String>,
+        work_dir: PathBuf,
+        /// Non-zero exit codes that still mean success
+        ok_codes: Vec<i32>,
+    },
     /// Clone a repo that doesn't exist yet
-    Clone { url: String, dest: PathBuf },
+    Clone {
+        vcs: Vcs,
+        spec: CloneSpec,
+        dest: PathBuf,
+    },
     /// Run an arbitrary shell command
     Shell { cmd: String, work_dir: PathBuf },
     /// Nothing to do (e.g. checkout for already-existing repo)
@@ -19,28 +40,81 @@ pub enum Operation {
     NotCheckedOut,
 }
 
+impl Operation {
+    /// Whether the operation talks to the repo's remote host
+    pub fn uses_remote(&self) -> bool {
+        match self {
+            Operation::Git { args, .. } | Operation::Exec { args, .. } => is_remote_command(args),
+            Operation::GitSeq { commands, .. } => commands.iter().any(|a| is_remote_command(a)),
+            Operation::Clone { .. } => true,
+            Operation::Shell { .. } | Operation::Skip { .. } | Operation::NotCheckedOut => false,
+        }
+    }
+}
+
+/// git, hg or jj subcommands that contact a remote
+fn is_remote_command(args: &[String]) -> bool {
+    let words: Vec<&str> = args
+        .iter()
+        .map(String::as_str)
+        .filter(|a| !a.starts_with('-'))
+        .take(2)
+        .collect();
+    matches!(
+        words.as_slice(),
+        ["fetch" | "pull" | "push" | "clone", ..]
+            | ["submodule", "update"]
+            | ["git", "fetch" | "push" | "clone"]
+    )
+}
+
 pub fn plan(command: &Command, repo: &Repo) -> Operation {
+    match repo.vcs {
+        Vcs::Git => plan_git(command, repo),
+        Vcs::Hg | Vcs::Jj | Vcs::Plain => plan_other(command, repo),
+    }
+}
+
+fn plan_git(command: &Command, repo: &Repo) -> Operation {
     let exists = repo.path.is_dir();
 
     match command {
-        Command::Update | Command::Pull => {
+        Command::Update { fetch } | Command::Pull { fetch } => {
             if exists {
-                Operation::Git {
-                    args: vec!["pull".into()],
-                    work_dir: repo.path.clone(),
-                }
-            } else if let Some(url) = &repo.clone_url {
-                Operation::Clone {
-                    url: url.clone(),
-                    dest: repo.path.clone(),
+                if repo.submodules || fetch.recurse_submodules {
+                    fetch_op(
+                        repo,
+                        fetch,
+                        vec![
+                            git_args(&["pull", "--recurse-submodules"]),
+                            git_args(&["submodule", "update", "--init", "--recursive"]),
+                        ],
+                    )
+                } else {
+                    fetch_op(repo, fetch, vec![git_args(&["pull"])])
                 }
+            } else if repo.clone.is_some() {
+                let opts = CloneOpts {
+                    recurse_submodules: fetch.recurse_submodules,
+                    ..Default::default()
+                };
+                clone_op(repo, &opts)
             } else {
                 Operation::NotCheckedOut
             }
         }
 
         Command::Status => {
-            if exists {
+            if exists && repo.path.join(".gitmodules").exists() {
+                // Submodule status lets the summary tell submodules apart
+                Operation::GitSeq {
+                    commands: vec![
+                        git_args(&["status", "--short"]),
+                        git_args(&["submodule", "status", "--recursive"]),
+                    ],
+                    work_dir: repo.path.clone(),
+                }
+            } else if exists {
                 Operation::Git {
                     args: vec!["status".into(), "--short".into()],
                     work_dir: repo.path.clone(),
@@ -72,27 +146,29 @@ pub fn plan(command: &Command, repo: &Repo) -> Operation {
             }
         }
 
-        Command::Fetch => {
+        Command::Fetch { fetch } => {
             if exists {
-                Operation::Git {
-                    args: vec!["fetch".into()],
-                    work_dir: repo.path.clone(),
+                if repo.submodules || fetch.recurse_submodules {
+                    fetch_op(
+                        repo,
+                        fetch,
+                        vec![git_args(&["fetch", "--recurse-submodules"])],
+                    )
+                } else {
+                    fetch_op(repo, fetch, vec![git_args(&["fetch"])])
                 }
             } else {
                 Operation::NotCheckedOut
             }
         }
 
-        Command::Checkout | Command::Co => {
+        Command::Checkout { clone: opts } | Command::Co { clone: opts } => {
             if exists {
                 Operation::Skip {
                     reason: "already exists".into(),
                 }
-            } else if let Some(url) = &repo.clone_url {
-                Operation::Clone {
-                    url: url.clone(),
-                    dest: repo.path.clone(),
-                }
+            } else if repo.clone.is_some() {
+                clone_op(repo, opts)
             } else {
                 Operation::Skip {
                     reason: "no clone URL".into(),
@@ -100,6 +176,47 @@ pub fn plan(command: &Command, repo: &Repo) -> Operation {
             }
         }
 
+        Command::Unshallow => {
+            if !exists {
+                Operation::NotCheckedOut
+            } else if vcs::is_shallow(&repo.path) {
+                Operation::Git {
+                    args: vec!["fetch".into(), "--unshallow".into()],
+                    work_dir: repo.path.clone(),
+                }
+            } else {
+                Operation::Skip {
+                    reason: "not shallow".into(),
+                }
+            }
+        }
+
+        Command::Mirror {
+            action: MirrorAction::Update,
+        } => mirror::plan_update(&mirror::cache_dir(), repo),
+
+        Command::Worktree {
+            action: WorktreeAction::Add { branch, root, .. },
+        } => {
+            if !exists {
+                Operation::NotCheckedOut
+            } else {
+                let dest = worktree::worktree_path(repo, root.as_deref(), branch);
+                // Another branch can map to the same directory; let git
+                // report that instead of skipping it
+                if worktree::checked_out_branch(&dest).as_ref() == Some(branch) {
+                    Operation::Skip {
+                        reason: format!("{} already exists", dest.display()),
+                    }
+                } else {
+                    Operation::Shell {
+                        cmd: worktree::add_command(branch, &dest),
+                        work_dir: repo.path.clone(),
+                    }
+                }
+            }
+        }
+
         Command::Run { cmd } => {
             let full_cmd = cmd.join(" ");
             if exists {
@@ -112,8 +229,151 @@ pub fn plan(command: &Command, repo: &Repo) -> Operation {
             }
         }
 
-        Command::List | Command::Ls | Command::Register => {
+        Command::List { .. }
+        | Command::Ls { .. }
+        | Command::Doctor
+        | Command::Last
+        | Command::History { .. }
+        | Command::Worktrees
+        | Command::Register { .. }
+        | Command::Discover { .. }
+        | Command::Unregister { .. }
+        | Command::Config { .. } => {
             unreachable!("command doesn't use operations")
         }
     }
 }
+
+/// Map commands to hg and jj, or skip them for plain directories
+fn plan_other(command: &Command, repo: &Repo) -> Operation {
+    let exists = repo.path.is_dir();
+
+    match command {
+        Command::Run { .. } => return plan_git(command, repo),
+        Command::Update { .. }
+        | Command::Pull { .. }
+        | Command::Checkout { .. }
+        | Command::Co { .. }
+            if !exists =>
+        {
+            return match &repo.clone {
+                Some(spec) if repo.vcs != Vcs::Plain => Operation::Clone {
+                    vcs: repo.vcs,
+                    spec: spec.clone(),
+                    dest: repo.path.clone(),
+                },
+                _ if matches!(command, Command::Checkout { .. } | Command::Co { .. }) => {
+                    Operation::Skip {
+                        reason: "no clone URL".into(),
+                    }
+                }
+                _ => Operation::NotCheckedOut,
+            };
+        }
+        Command::Checkout { .. } | Command::Co { .. } => {
+            return Operation::Skip {
+                reason: "already exists".into(),
+            }
+        }
+        _ if !exists => return Operation::NotCheckedOut,
+        _ => {}
+    }
+
+    // hg returns 1 from push when there's nothing to push
+    let (args, ok_codes): (&[&str], Vec<i32>) = match (repo.vcs, command) {
+        (Vcs::Hg, Command::Update { .. } | Command::Pull { .. }) => (&["pull", "--update"], vec![]),
+        (Vcs::Hg, Command::Fetch { .. }) => (&["pull"], vec![]),
+        (Vcs::Hg, Command::Status) => (&["status"], vec![]),
+        (Vcs::Hg, Command::Diff) => (&["diff"], vec![]),
+        (Vcs::Hg, Command::Push) => (&["push"], vec![1]),
+        (Vcs::Jj, Command::Update { .. } | Command::Pull { .. } | Command::Fetch { .. }) => {
+            (&["git", "fetch"], vec![])
+        }
+        (Vcs::Jj, Command::Status) => (&["status", "--color=never"], vec![]),
+        (Vcs::Jj, Command::Diff) => (&["diff", "--git", "--color=never"], vec![]),
+        (Vcs::Jj, Command::Push) => (&["git", "push"], vec![]),
+        _ => {
+            return Operation::Skip {
+                reason: format!(
+                    "{} not supported for {}",
+                    command.display_name(),
+                    repo.vcs.name()
+                ),
+            }
+        }
+    };
+
+    Operation::Exec {
+        program: repo.vcs.program(),
+        args: git_args(args),
+        work_dir: repo.path.clone(),
+        ok_codes,
+    }
+}
+
+/// Fetch-like git commands, optionally preceded by a fetch from the mirror
+fn fetch_op(repo: &Repo, opts: &FetchOpts, mut commands: Vec<Vec<String>>) -> Operation {
+    if let Some(path) = mirror::find(repo).filter(|_| opts.from_mirror) {
+        commands.insert(0, mirror::prefetch_args(&path));
+    }
+    if commands.len() == 1 {
+        Operation::Git {
+            args: commands.remove(0),
+            work_dir: repo.path.clone(),
+        }
+    } else {
+        Operation::GitSeq {
+            commands,
+            work_dir: repo.path.clone(),
+        }
+    }
+}
+
+fn git_args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+}
+
+/// Clone a missing repo, borrowing objects from its mirror if there is one
+fn clone_op(repo: &Repo, opts: &CloneOpts) -> Operation {
+    let mut spec = repo.clone.clone().expect("clone_op needs a clone spec");
+    if spec.reference.is_none() {
+        if let Some(path) = mirror::find(repo) {
+            // Copy the objects so clearing the cache can't break checkouts
+            spec.reference = Some(path);
+            spec.dissociate = true;
+        }
+    }
+    apply_clone_opts(&mut spec, opts);
+    Operation::Clone {
+        vcs: Vcs::Git,
+        spec,
+        dest: repo.path.clone(),
+    }
+}
+
+/// Apply command-line clone options on top of the repo's own settings
+fn apply_clone_opts(spec: &mut CloneSpec, opts: &CloneOpts) {
+    if let Some(depth) = opts.depth {
+        spec.depth = Some(depth);
+    } else if opts.shallow {
+        spec.depth = Some(1);
+    }
+    if let Some(filter) = &opts.filter {
+        spec.filter = Some(filter.clone());
+    }
+    if opts.single_branch {
+        spec.single_branch = true;
+    }
+    if opts.recurse_submodules {
+        spec.recurse_submodules = true;
+    }
+    if let Some(dir) = &opts.reference_dir {
+        let path = mirror::mirror_path(dir, &spec.url);
+        if path.is_dir() {
+            spec.reference = Some(path);
+        }
+    }
+    if opts.dissociate {
+        spec.dissociate = true;
+    }
+}
@@ -0,0 +1,486 @@
+use crate::config::Repo;
+use crate::config_writer::ConfigFile;
+use crate::shellwords;
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command as StdCommand;
+
+/// What a repo directory should be registered as
+enum Entry {
+    /// Clone from a remote URL
+    Remote { url: String },
+    /// No remotes at all: a section without a checkout command
+    LocalOnly,
+}
+
+pub fn register(config_path: &Path, base_dir: &Path, paths: &[PathBuf], remote: &str) {
+    let cwd = std::env::current_dir().expect("cannot determine current directory");
+    let targets: Vec<PathBuf> = if paths.is_empty() {
+        vec![cwd]
+    } else {
+        paths.iter().map(|p| absolute_path(&cwd.join(p))).collect()
+    };
+
+    let mut file = load_config_file(config_path);
+    let mut failed = false;
+    let mut changed = false;
+
+    for target in &targets {
+        match register_one(&mut file, base_dir, target, remote) {
+            Ok(true) => changed = true,
+            Ok(false) => {}
+            Err(msg) => {
+                eprintln!("error: {}", msg);
+                failed = true;
+            }
+        }
+    }
+
+    if changed {
+        save_config_file(&file, config_path);
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Find git repos under `dir` that aren't in the config yet, preview them,
+/// and register them all after confirmation.
+pub fn discover(config_path: &Path, base_dir: &Path, dir: Option<&Path>, remote: &str, yes: bool) {
+    let cwd = std::env::current_dir().expect("cannot determine current directory");
+    let root = match dir {
+        Some(d) => absolute_path(&cwd.join(d)),
+        None => absolute_path(base_dir),
+    };
+    let mut file = load_config_file(config_path);
+    let (found, outside) = find_unregistered(&file, base_dir, &root);
+
+    for msg in &outside {
+        eprintln!("skipped: {}", msg);
+    }
+    if found.is_empty() {
+        println!("no unregistered repos under {}", root.display());
+        if !outside.is_empty() {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let width = found.iter().map(|(s, _)| s.len()).max().unwrap_or(0);
+    println!("found {} unregistered repos:", found.len());
+    for (section, path) in &found {
+        let desc = match detect_entry(path, remote) {
+            Ok(Entry::Remote { url }) => url,
+            Ok(Entry::LocalOnly) => "(local only)".into(),
+            Err(msg) => format!("(skipped: {})", msg),
+        };
+        println!("  {:width$}  {}", section, desc, width = width);
+    }
+
+    if !yes && !confirm("register all?") {
+        return;
+    }
+
+    let mut changed = false;
+    for (_, path) in &found {
+        match register_one(&mut file, base_dir, path, remote) {
+            Ok(c) => changed |= c,
+            Err(msg) => eprintln!("error: {}", msg),
+        }
+    }
+    if changed {
+        save_config_file(&file, config_path);
+    }
+    if !outside.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+/// Git repos under `root` without a section in `file`, with their section
+/// paths, plus an error for each repo that can't be registered because it
+/// isn't under the base dir.
+fn find_unregistered(
+    file: &ConfigFile,
+    base_dir: &Path,
+    root: &Path,
+) -> (Vec<(String, PathBuf)>, Vec<String>) {
+    let registered: HashSet<String> = file.sections().into_iter().collect();
+    let base = absolute_path(base_dir);
+    let mut found = Vec::new();
+    let mut outside = Vec::new();
+    for path in find_git_repos(root) {
+        // A repo at the base dir itself can't have a section
+        if path == base {
+            continue;
+        }
+        match section_for_path(&path, base_dir) {
+            Ok(section) if registered.contains(&section) => {}
+            Ok(section) => found.push((section, path)),
+            Err(msg) => outside.push(msg),
+        }
+    }
+    (found, outside)
+}
+
+pub fn unregister(config_path: &Path, base_dir: &Path, path: Option<&Path>) {
+    let cwd = std::env::current_dir().expect("cannot determine current directory");
+    let target = match path {
+        Some(p) => absolute_path(&cwd.join(p)),
+        None => cwd,
+    };
+    let section = section_for_path(&target, base_dir).unwrap_or_else(|msg| {
+        eprintln!("error: {}", msg);
+        std::process::exit(1);
+    });
+
+    let mut file = load_config_file(config_path);
+    if !file.remove_section(&section) {
+        eprintln!("error: not registered: {}", section);
+        std::process::exit(1);
+    }
+    save_config_file(&file, config_path);
+    println!("unregistered {}", section);
+}
+
+pub fn config_set(config_path: &Path, repos: &[Repo], repo: &str, key: &str, value: &str) {
+    let mut file = load_config_file(config_path);
+
+    let Some(section) = resolve_section(&file, repos, repo) else {
+        eprintln!("error: no repo named {}", repo);
+        std::process::exit(1);
+    };
+
+    file.set(&section, key, value);
+    save_config_file(&file, config_path);
+    println!("{}: {} = {}", section, key, value);
+}
+
+/// Section in `file` for a section path or a repo's unique name
+fn resolve_section(file: &ConfigFile, repos: &[Repo], repo: &str) -> Option<String> {
+    let section = if file.has_section(repo) {
+        repo
+    } else {
+        &repos.iter().find(|r| r.name == repo)?.section
+    };
+    file.has_section(section).then(|| section.to_string())
+}
+
+/// Add or update one repo's section. Returns whether the file changed.
+fn register_one(
+    file: &mut ConfigFile,
+    base_dir: &Path,
+    path: &Path,
+    remote: &str,
+) -> Result<bool, String> {
+    let section = section_for_path(path, base_dir)?;
+    let entry = detect_entry(path, remote)?;
+
+    let repo_name = path
+        .file_name()
+        .ok_or_else(|| format!("cannot determine directory name of {}", path.display()))?
+        .to_string_lossy();
+
+    match entry {
+        Entry::Remote { url } => {
+            let checkout = format!(
+                "git clone {} {}",
+                shellwords::quote(&url),
+                shellwords::quote(&repo_name)
+            );
+            match file.get(&section, "checkout") {
+                Some(existing) if existing == checkout => {
+                    eprintln!("already registered: {}", section);
+                    Ok(false)
+                }
+                Some(_) => {
+                    file.set(&section, "checkout", &checkout);
+                    println!("updated {} ({})", section, url);
+                    Ok(true)
+                }
+                None => {
+                    file.set(&section, "checkout", &checkout);
+                    println!("registered {} ({})", section, url);
+                    Ok(true)
+                }
+            }
+        }
+        Entry::LocalOnly => {
+            if file.has_section(&section) {
+                eprintln!("already registered: {}", section);
+                Ok(false)
+            } else {
+                file.add_section(&section);
+                println!("registered {} (local only)", section);
+                Ok(true)
+            }
+        }
+    }
+}
+
+/// Work out how to register a repo directory: the URL of `remote` if it
+/// exists, local-only if the repo has no remotes at all.
+fn detect_entry(path: &Path, remote: &str) -> Result<Entry, String> {
+    if !path.join(".git").exists() {
+        return Err(format!("{} is not a git repo", path.display()));
+    }
+
+    if let Some(url) = git_output(path, &["remote", "get-url", remote]) {
+        return Ok(Entry::Remote { url });
+    }
+
+    let remotes = git_output(path, &["remote"]).unwrap_or_default();
+    if remotes.is_empty() {
+        Ok(Entry::LocalOnly)
+    } else {
+        Err(format!(
+            "{} has no '{}' remote (available: {})",
+            path.display(),
+            remote,
+            remotes.lines().collect::<Vec<_>>().join(", ")
+        ))
+    }
+}
+
+fn git_output(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = StdCommand::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        None
+    }
+}
+
+/// Recursively find git repos under `root`, including repos nested inside
+/// other repos. Below a repo, only directories with their own `.git`
+/// directory count: a `.git` file there is a submodule or linked worktree of
+/// the outer repo. Doesn't descend into hidden directories or symlinks.
+pub fn find_git_repos(root: &Path) -> Vec<PathBuf> {
+    let mut repos = Vec::new();
+    let mut stack = vec![(root.to_path_buf(), false)];
+
+    while let Some((dir, in_repo)) = stack.pop() {
+        let git = dir.join(".git");
+        let is_repo = if in_repo { git.is_dir() } else { git.exists() };
+        if is_repo {
+            repos.push(dir.clone());
+        }
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(e) => e,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if is_dir && !hidden {
+                stack.push((entry.path(), in_repo || is_repo));
+            }
+        }
+    }
+
+    repos.sort();
+    repos
+}
+
+fn confirm(prompt: &str) -> bool {
+    print!("{} [y/N] ", prompt);
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// Compute the relative section path of a repo directory from base_dir
+fn section_for_path(path: &Path, base_dir: &Path) -> Result<String, String> {
+    let base_dir = absolute_path(base_dir);
+    match path.strip_prefix(&base_dir) {
+        Ok(rel) if rel.as_os_str().is_empty() => {
+            Err(format!("{} is the base dir itself", path.display()))
+        }
+        Ok(rel) => Ok(rel.to_string_lossy().to_string()),
+        Err(_) => Err(format!(
+            "{} is not under base dir {}",
+            path.display(),
+            base_dir.display()
+        )),
+    }
+}
+
+/// Canonicalize a path, falling back to resolving `.` and `..` lexically
+/// for paths that don't exist (e.g. deleted checkouts).
+pub fn absolute_path(path: &Path) -> PathBuf {
+    if let Ok(p) = path.canonicalize() {
+        return p;
+    }
+    let mut out = PathBuf::new();
+    for comp in path.components() {
+        match comp {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+fn load_config_file(config_path: &Path) -> ConfigFile {
+    ConfigFile::load(config_path).unwrap_or_else(|e| {
+        eprintln!("error: cannot read {}: {}", config_path.display(), e);
+        std::process::exit(1);
+    })
+}
+
+fn save_config_file(file: &ConfigFile, config_path: &Path) {
+    file.save().unwrap_or_else(|e| {
+        eprintln!("error: cannot write {}: {}", config_path.display(), e);
+        std::process::exit(1);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{git, init_repo, TempDir};
+
+    fn empty_config(base: &Path) -> ConfigFile {
+        ConfigFile::load(&base.join(".mrconfig")).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_section_by_path_or_name() {
+        let tmp = TempDir::new("config-set");
+        let config_path = tmp.path().join(".mrconfig");
+        std::fs::write(
+            &config_path,
+            "[src/MyRepo]\ncheckout = git clone 'url' 'MyRepo'\n\n[lib/Other]\nname = Tools\n",
+        )
+        .unwrap();
+        let repos = crate::config::parse_config(&config_path, tmp.path());
+        let file = ConfigFile::load(&config_path).unwrap();
+
+        let resolve = |repo| resolve_section(&file, &repos, repo);
+        assert_eq!(resolve("MyRepo").as_deref(), Some("src/MyRepo"));
+        assert_eq!(resolve("src/MyRepo").as_deref(), Some("src/MyRepo"));
+        assert_eq!(resolve("Tools").as_deref(), Some("lib/Other"));
+        assert_eq!(resolve("myrepo"), None);
+        assert_eq!(resolve("missing"), None);
+        assert_eq!(repos[1].path, tmp.path().join("src/MyRepo"));
+    }
+
+    #[test]
+    fn test_register_remote_and_reregister() {
+        let tmp = TempDir::new("register");
+        let repo = tmp.path().join("team/api");
+        init_repo(&repo);
+        git(
+            &repo,
+            &["remote", "add", "origin", "git@github.com:org/api.git"],
+        );
+
+        let mut file = empty_config(tmp.path());
+        assert_eq!(
+            register_one(&mut file, tmp.path(), &repo, "origin"),
+            Ok(true)
+        );
+        assert_eq!(
+            file.get("team/api", "checkout").as_deref(),
+            Some("git clone 'git@github.com:org/api.git' 'api'")
+        );
+        assert_eq!(
+            register_one(&mut file, tmp.path(), &repo, "origin"),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn test_register_chooses_remote() {
+        let tmp = TempDir::new("register-remote");
+        let repo = tmp.path().join("fork");
+        init_repo(&repo);
+        git(
+            &repo,
+            &["remote", "add", "upstream", "https://example.com/fork"],
+        );
+
+        let mut file = empty_config(tmp.path());
+        let err = register_one(&mut file, tmp.path(), &repo, "origin").unwrap_err();
+        assert!(
+            err.contains("no 'origin' remote (available: upstream)"),
+            "{}",
+            err
+        );
+        assert_eq!(
+            register_one(&mut file, tmp.path(), &repo, "upstream"),
+            Ok(true)
+        );
+        assert_eq!(
+            file.get("fork", "checkout").as_deref(),
+            Some("git clone 'https://example.com/fork' 'fork'")
+        );
+    }
+
+    #[test]
+    fn test_register_local_only() {
+        let tmp = TempDir::new("register-local");
+        let repo = tmp.path().join("scratch");
+        init_repo(&repo);
+
+        let mut file = empty_config(tmp.path());
+        assert_eq!(
+            register_one(&mut file, tmp.path(), &repo, "origin"),
+            Ok(true)
+        );
+        assert!(file.has_section("scratch"));
+        assert_eq!(file.get("scratch", "checkout"), None);
+        assert_eq!(
+            register_one(&mut file, tmp.path(), &repo, "origin"),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn test_register_outside_base_dir() {
+        let tmp = TempDir::new("register-outside");
+        let base = tmp.path().join("base");
+        let repo = tmp.path().join("elsewhere");
+        std::fs::create_dir_all(&base).unwrap();
+        init_repo(&repo);
+
+        let mut file = empty_config(&base);
+        let err = register_one(&mut file, &base, &repo, "origin").unwrap_err();
+        assert!(err.contains("is not under base dir"), "{}", err);
+    }
+
+    #[test]
+    fn test_find_unregistered() {
+        let tmp = TempDir::new("discover");
+        let base = tmp.path().join("base");
+        init_repo(&base.join("a"));
+        init_repo(&base.join("group/b"));
+        init_repo(&tmp.path().join("outside"));
+
+        let mut file = empty_config(&base);
+        file.add_section("a");
+
+        let (found, outside) = find_unregistered(&file, &base, &base);
+        assert_eq!(found, vec![("group/b".to_string(), base.join("group/b"))]);
+        assert!(outside.is_empty());
+
+        let (found, outside) = find_unregistered(&file, &base, tmp.path());
+        assert_eq!(found, vec![("group/b".to_string(), base.join("group/b"))]);
+        assert_eq!(outside.len(), 1);
+        assert!(
+            outside[0].contains("is not under base dir"),
+            "{}",
+            outside[0]
+        );
+    }
+}
@@ -0,0 +1,171 @@
+/// Split a shell command line into simple commands and their words,
+/// following POSIX quoting rules.
+///
+/// Unquoted `;`, newlines, `&`, `|`, `&&` and `||` separate commands. Expansions
+/// (`$var`, globs, backticks) are not performed; their text is kept as-is.
+pub fn split_commands(line: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut commands: Vec<Vec<String>> = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut word = String::new();
+    // Distinguishes an empty quoted word ('') from no word at all
+    let mut in_word = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated single quote".into()),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            // Inside double quotes a backslash only escapes these
+                            Some(c @ ('$' | '`' | '"' | '\\')) => word.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("unterminated double quote".into()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated double quote".into()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(c) => {
+                    in_word = true;
+                    word.push(c);
+                }
+                None => return Err("trailing backslash".into()),
+            },
+            ';' | '\n' | '&' | '|' => {
+                if matches!(c, '&' | '|') && chars.peek() == Some(&c) {
+                    chars.next();
+                }
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+                if !words.is_empty() {
+                    commands.push(std::mem::take(&mut words));
+                }
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+
+    if in_word {
+        words.push(word);
+    }
+    if !words.is_empty() {
+        commands.push(words);
+    }
+    Ok(commands)
+}
+
+/// Quote a word so `split_commands` (and sh) reads it back unchanged.
+pub fn quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+/// Join words into a command line, quoting only the words that need it
+pub fn join(words: &[String]) -> String {
+    words
+        .iter()
+        .map(|w| {
+            let plain = !w.is_empty()
+                && w.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+            if plain {
+                w.clone()
+            } else {
+                quote(w)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<Vec<String>> {
+        split_commands(line).unwrap()
+    }
+
+    #[test]
+    fn test_quoting() {
+        assert_eq!(
+            words(r#"git clone 'a b' "c \"d\" \$e" f\ g '' x"#),
+            vec![vec!["git", "clone", "a b", r#"c "d" $e"#, "f g", "", "x"]]
+        );
+    }
+
+    #[test]
+    fn test_operators_split_commands() {
+        assert_eq!(
+            words("git clone url dir && cd dir; make|tee log"),
+            vec![
+                vec!["git", "clone", "url", "dir"],
+                vec!["cd", "dir"],
+                vec!["make"],
+                vec!["tee", "log"],
+            ]
+        );
+        assert_eq!(words("echo 'a && b'"), vec![vec!["echo", "a && b"]]);
+        assert_eq!(
+            words("git clone url dir\n\ncd dir\\\n  && make 'a\nb'"),
+            vec![
+                vec!["git", "clone", "url", "dir"],
+                vec!["cd", "dir"],
+                vec!["make", "a\nb"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_quote() {
+        assert!(split_commands("git clone 'url").is_err());
+        assert!(split_commands("git clone \"url").is_err());
+    }
+
+    #[test]
+    fn test_quote_round_trip() {
+        let word = "it's a \"url\"";
+        assert_eq!(words(&quote(word)), vec![vec![word]]);
+    }
+
+    #[test]
+    fn test_join_round_trip() {
+        let original: Vec<String> = ["run", "make test", "it's", "", "-j", "4"]
+            .iter()
+            .map(|w| w.to_string())
+            .collect();
+        let line = join(&original);
+        assert_eq!(line, r"run 'make test' 'it'\''s' '' -j 4");
+        assert_eq!(words(&line), vec![original]);
+    }
+}
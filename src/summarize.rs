@@ -1,30 +1,84 @@
 use crate::cli::Command;
+use crate::vcs::Vcs;
+use std::collections::HashSet;
 
-pub fn summarize(command: &Command, stdout: &str, stderr: &str, exit_code: i32) -> String {
+/// One-line description of an operation's result
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub text: String,
+    /// Whether there is anything to look at. False for results like a clean
+    /// status or an up-to-date pull, which the dirty filter leaves out.
+    pub changed: bool,
+}
+
+impl Summary {
+    fn changed(text: impl Into<String>) -> Summary {
+        Summary {
+            text: text.into(),
+            changed: true,
+        }
+    }
+
+    fn quiet(text: impl Into<String>) -> Summary {
+        Summary {
+            text: text.into(),
+            changed: false,
+        }
+    }
+}
+
+pub fn summarize(
+    command: &Command,
+    vcs: Vcs,
+    stdout: &str,
+    stderr: &str,
+    exit_code: i32,
+) -> Summary {
     if exit_code != 0 {
         // Try to extract a useful error message
         let msg = first_meaningful_line(stderr)
             .or_else(|| first_meaningful_line(stdout))
             .unwrap_or_else(|| format!("exit code {}", exit_code));
-        return msg;
+        return Summary::changed(msg);
+    }
+
+    match (vcs, command) {
+        (_, Command::Run { .. }) => summarize_run(stdout),
+        (Vcs::Hg, _) => summarize_hg(command, stdout, stderr),
+        (Vcs::Jj, _) => summarize_jj(command, stdout, stderr),
+        (Vcs::Git | Vcs::Plain, _) => summarize_git(command, stdout, stderr),
     }
+}
 
+fn summarize_git(command: &Command, stdout: &str, stderr: &str) -> Summary {
     match command {
-        Command::Update | Command::Pull => summarize_pull(stdout, stderr),
+        Command::Update { .. } | Command::Pull { .. } => summarize_pull(stdout, stderr),
         Command::Status => summarize_status(stdout),
         Command::Diff => summarize_diff(stdout),
         Command::Push => summarize_push(stdout, stderr),
-        Command::Fetch => summarize_fetch(stdout, stderr),
-        Command::Checkout | Command::Co => summarize_clone(stderr),
+        Command::Fetch { .. } => summarize_fetch(stdout, stderr),
+        Command::Checkout { .. } | Command::Co { .. } => summarize_clone(stderr),
+        Command::Unshallow => Summary::changed("unshallowed"),
+        Command::Mirror { .. } => summarize_mirror(stdout, stderr),
+        Command::Worktree { .. } => summarize_worktree_add(stderr),
         Command::Run { .. } => summarize_run(stdout),
-        Command::List | Command::Ls | Command::Register => String::new(),
+        Command::List { .. }
+        | Command::Ls { .. }
+        | Command::Doctor
+        | Command::Last
+        | Command::History { .. }
+        | Command::Worktrees
+        | Command::Register { .. }
+        | Command::Discover { .. }
+        | Command::Unregister { .. }
+        | Command::Config { .. } => Summary::quiet(""),
     }
 }
 
-fn summarize_pull(stdout: &str, stderr: &str) -> String {
+fn summarize_pull(stdout: &str, stderr: &str) -> Summary {
     let combined = format!("{}\n{}", stdout, stderr);
     if combined.contains("Already up to date") || combined.contains("Already up-to-date") {
-        return "already up to date".into();
+        return Summary::quiet("already up to date");
     }
     // Look for "X files changed" summary
     for line in stdout.lines().chain(stderr.lines()) {
@@ -33,20 +87,51 @@ fn summarize_pull(stdout: &str, stderr: &str) -> String {
             || line.contains("insertions")
             || line.contains("deletions")
         {
-            return line.trim().to_string();
+            return Summary::changed(line.trim());
         }
     }
     if stdout.trim().is_empty() && stderr.trim().is_empty() {
-        "done".into()
+        Summary::changed("done")
     } else {
-        first_meaningful_line(stdout).unwrap_or_else(|| "done".into())
+        Summary::changed(first_meaningful_line(stdout).unwrap_or_else(|| "done".into()))
     }
 }
 
-fn summarize_status(stdout: &str) -> String {
-    let lines: Vec<&str> = stdout.lines().filter(|l| !l.trim().is_empty()).collect();
-    if lines.is_empty() {
-        return "clean".into();
+fn summarize_status(stdout: &str) -> Summary {
+    // Output may be followed by `git submodule status`, whose lines are a
+    // state character, a full hash and the submodule path
+    let (submodule_lines, lines): (Vec<&str>, Vec<&str>) = stdout
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .partition(|l| is_submodule_status_line(l));
+
+    let submodule_paths: HashSet<&str> = submodule_lines
+        .iter()
+        .filter_map(|l| l[1..].split_whitespace().nth(1))
+        .collect();
+    let mut out_of_sync: HashSet<&str> = submodule_lines
+        .iter()
+        .filter(|l| !l.starts_with(' '))
+        .filter_map(|l| l[1..].split_whitespace().nth(1))
+        .collect();
+
+    // A submodule with new commits or local changes also shows up in
+    // `status --short`; count it as a submodule, not a modified file
+    let lines: Vec<&str> = lines
+        .into_iter()
+        .filter(|l| {
+            let path = l.get(3..).unwrap_or("").trim();
+            if submodule_paths.contains(path) {
+                out_of_sync.insert(path);
+                false
+            } else {
+                true
+            }
+        })
+        .collect();
+
+    if lines.is_empty() && out_of_sync.is_empty() {
+        return Summary::quiet("clean");
     }
     let modified = lines
         .iter()
@@ -70,16 +155,29 @@ fn summarize_status(stdout: &str) -> String {
     if deleted > 0 {
         parts.push(format!("{} deleted", deleted));
     }
-    if parts.is_empty() {
-        format!("{} changed", lines.len())
-    } else {
-        parts.join(", ")
+    if parts.is_empty() && !lines.is_empty() {
+        parts.push(format!("{} changed", lines.len()));
+    }
+    if !out_of_sync.is_empty() {
+        parts.push(format!(
+            "{} submodule{} out of sync",
+            out_of_sync.len(),
+            if out_of_sync.len() == 1 { "" } else { "s" }
+        ));
     }
+    Summary::changed(parts.join(", "))
 }
 
-fn summarize_diff(stdout: &str) -> String {
+fn is_submodule_status_line(line: &str) -> bool {
+    let mut chars = line.chars();
+    let state_ok = matches!(chars.next(), Some(' ' | '+' | '-' | 'U'));
+    let hash: String = chars.take_while(|c| !c.is_whitespace()).collect();
+    state_ok && hash.len() >= 40 && hash.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn summarize_diff(stdout: &str) -> Summary {
     if stdout.trim().is_empty() {
-        return "no changes".into();
+        return Summary::quiet("no changes");
     }
     let plus = stdout
         .lines()
@@ -89,52 +187,176 @@ fn summarize_diff(stdout: &str) -> String {
         .lines()
         .filter(|l| l.starts_with('-') && !l.starts_with("---"))
         .count();
-    let files: std::collections::HashSet<&str> = stdout
-        .lines()
-        .filter(|l| l.starts_with("diff --git"))
-        .collect();
-    format!("{} files, +{} -{}", files.len(), plus, minus)
+    let files: HashSet<&str> = stdout.lines().filter(|l| l.starts_with("diff ")).collect();
+    Summary::changed(format!("{} files, +{} -{}", files.len(), plus, minus))
 }
 
-fn summarize_push(stdout: &str, stderr: &str) -> String {
+fn summarize_push(stdout: &str, stderr: &str) -> Summary {
     let combined = format!("{}\n{}", stdout, stderr);
     if combined.contains("Everything up-to-date") {
-        return "up to date".into();
+        return Summary::quiet("up to date");
     }
     for line in stderr.lines().chain(stdout.lines()) {
         if line.contains("->") {
-            return line.trim().to_string();
+            return Summary::changed(line.trim());
         }
     }
-    "done".into()
+    Summary::changed("done")
 }
 
-fn summarize_fetch(stdout: &str, stderr: &str) -> String {
+fn summarize_fetch(stdout: &str, stderr: &str) -> Summary {
     if stdout.trim().is_empty() && stderr.trim().is_empty() {
-        return "up to date".into();
+        return Summary::quiet("up to date");
     }
     let new_refs: Vec<&str> = stderr.lines().filter(|l| l.contains("->")).collect();
     if new_refs.is_empty() {
-        "up to date".into()
+        Summary::quiet("up to date")
     } else {
-        format!("{} updated refs", new_refs.len())
+        Summary::changed(format!("{} updated refs", new_refs.len()))
     }
 }
 
-fn summarize_clone(stderr: &str) -> String {
+fn summarize_clone(stderr: &str) -> Summary {
     if stderr.contains("Cloning into") {
-        "cloned".into()
+        Summary::changed("cloned")
+    } else {
+        Summary::changed("done")
+    }
+}
+
+fn summarize_mirror(stdout: &str, stderr: &str) -> Summary {
+    if stderr.contains("Cloning into bare repository") {
+        Summary::changed("mirrored")
     } else {
-        "done".into()
+        summarize_fetch(stdout, stderr)
     }
 }
 
-fn summarize_run(stdout: &str) -> String {
+fn summarize_worktree_add(stderr: &str) -> Summary {
+    let text = stderr
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("Preparing worktree "))
+        .map(|l| l.trim_matches(|c| c == '(' || c == ')').to_string())
+        .unwrap_or_else(|| "added".into());
+    Summary::changed(text)
+}
+
+fn summarize_run(stdout: &str) -> Summary {
     let lines: Vec<&str> = stdout.lines().filter(|l| !l.trim().is_empty()).collect();
     match lines.len() {
-        0 => "done (no output)".into(),
-        1 => lines[0].trim().to_string(),
-        n => format!("{} ({}+ lines)", lines[0].trim(), n),
+        0 => Summary::quiet("done (no output)"),
+        1 => Summary::changed(lines[0].trim()),
+        n => Summary::changed(format!("{} ({}+ lines)", lines[0].trim(), n)),
+    }
+}
+
+fn summarize_hg(command: &Command, stdout: &str, stderr: &str) -> Summary {
+    let combined = format!("{}\n{}", stdout, stderr);
+    match command {
+        Command::Update { .. } | Command::Pull { .. } | Command::Fetch { .. } => {
+            if combined.contains("no changes found") {
+                return Summary::quiet("already up to date");
+            }
+            let text = combined
+                .lines()
+                .find(|l| l.starts_with("added "))
+                .map(|l| l.trim().to_string())
+                .unwrap_or_else(|| "done".into());
+            Summary::changed(text)
+        }
+        Command::Push => {
+            if combined.contains("no changes found") {
+                Summary::quiet("up to date")
+            } else {
+                Summary::changed("pushed")
+            }
+        }
+        // `hg status` uses single-letter codes: M A R ! ?
+        Command::Status => count_status_codes(
+            stdout,
+            &[
+                ("modified", &['M']),
+                ("added", &['A']),
+                ("untracked", &['?']),
+                ("deleted", &['R', '!']),
+            ],
+        ),
+        Command::Diff => summarize_diff(stdout),
+        Command::Checkout { .. } | Command::Co { .. } => Summary::changed("cloned"),
+        _ => Summary::changed(first_meaningful_line(stdout).unwrap_or_else(|| "done".into())),
+    }
+}
+
+fn summarize_jj(command: &Command, stdout: &str, stderr: &str) -> Summary {
+    let combined = format!("{}\n{}", stdout, stderr);
+    match command {
+        Command::Update { .. } | Command::Pull { .. } | Command::Fetch { .. } => {
+            let updated = combined
+                .lines()
+                .filter(|l| l.contains("[updated]") || l.contains("[new]"))
+                .count();
+            if updated == 0 {
+                Summary::quiet("up to date")
+            } else {
+                Summary::changed(format!("{} updated bookmarks", updated))
+            }
+        }
+        Command::Push => {
+            if combined.contains("Nothing changed") {
+                Summary::quiet("up to date")
+            } else {
+                Summary::changed("pushed")
+            }
+        }
+        Command::Status => {
+            if stdout.contains("The working copy has no changes") {
+                return Summary::quiet("clean");
+            }
+            let changes: String = stdout
+                .lines()
+                .skip_while(|l| !l.starts_with("Working copy changes:"))
+                .skip(1)
+                .take_while(|l| l.len() > 2 && l.as_bytes()[1] == b' ')
+                .map(|l| format!("{}\n", l))
+                .collect();
+            count_status_codes(
+                &changes,
+                &[
+                    ("modified", &['M', 'R']),
+                    ("added", &['A', 'C']),
+                    ("deleted", &['D']),
+                ],
+            )
+        }
+        Command::Diff => summarize_diff(stdout),
+        Command::Checkout { .. } | Command::Co { .. } => Summary::changed("cloned"),
+        _ => Summary::changed(first_meaningful_line(stdout).unwrap_or_else(|| "done".into())),
+    }
+}
+
+/// Summarize `X path` status lines by their one-letter code, counting
+/// the codes of each label together
+fn count_status_codes(stdout: &str, labels: &[(&str, &[char])]) -> Summary {
+    let codes: Vec<char> = stdout
+        .lines()
+        .filter(|l| l.len() > 2 && l.as_bytes()[1] == b' ')
+        .filter_map(|l| l.chars().next())
+        .collect();
+    if codes.is_empty() {
+        return Summary::quiet("clean");
+    }
+    let count = |set: &[char]| codes.iter().filter(|c| set.contains(c)).count();
+    let mut parts = Vec::new();
+    for (label, set) in labels {
+        let n = count(set);
+        if n > 0 {
+            parts.push(format!("{} {}", n, label));
+        }
+    }
+    if parts.is_empty() {
+        Summary::changed(format!("{} changed", codes.len()))
+    } else {
+        Summary::changed(parts.join(", "))
     }
 }
 
@@ -150,3 +372,85 @@ fn first_meaningful_line(s: &str) -> Option<String> {
             }
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize_status_counts_submodules_separately() {
+        let stdout = "\
+ M src/main.rs
+ M vendor/lib
+?? notes.txt
+ 1111111111111111111111111111111111111111 vendor/lib (v1.0)
++2222222222222222222222222222222222222222 vendor/proto (heads/main)
+-3333333333333333333333333333333333333333 vendor/docs
+ 4444444444444444444444444444444444444444 vendor/ok (v2.0)
+";
+        assert_eq!(
+            summarize_status(stdout).text,
+            "1 modified, 1 untracked, 3 submodules out of sync"
+        );
+    }
+
+    #[test]
+    fn test_summarize_hg_status() {
+        let status = Command::Status;
+        let stdout = "M src/lib.rs\nA new.rs\n? scratch.txt\n! gone.rs\n";
+        assert_eq!(
+            summarize(&status, Vcs::Hg, stdout, "", 0).text,
+            "1 modified, 1 added, 1 untracked, 1 deleted"
+        );
+        assert_eq!(
+            summarize(&status, Vcs::Hg, "", "", 0),
+            Summary::quiet("clean")
+        );
+    }
+
+    #[test]
+    fn test_summarize_jj_status() {
+        let status = Command::Status;
+        let stdout = "\
+Working copy changes:
+M src/lib.rs
+A src/new.rs
+Working copy : qpvuntsm 12345678 (no description set)
+Parent commit: zzzzzzzz 00000000 main
+";
+        assert_eq!(
+            summarize(&status, Vcs::Jj, stdout, "", 0).text,
+            "1 modified, 1 added"
+        );
+        let clean = "The working copy has no changes.\nWorking copy : x\n";
+        assert_eq!(
+            summarize(&status, Vcs::Jj, clean, "", 0),
+            Summary::quiet("clean")
+        );
+    }
+
+    #[test]
+    fn test_summarize_status_clean_with_submodules() {
+        let stdout = " 4444444444444444444444444444444444444444 vendor/ok (v2.0)\n";
+        assert_eq!(summarize_status(stdout).text, "clean");
+    }
+
+    #[test]
+    fn test_summarize_flags_nothing_to_report() {
+        let pull = Command::Pull {
+            fetch: Default::default(),
+        };
+        let up_to_date = summarize(&pull, Vcs::Git, "Already up to date.\n", "", 0);
+        assert_eq!(up_to_date, Summary::quiet("already up to date"));
+        let pulled = summarize(
+            &pull,
+            Vcs::Git,
+            " 2 files changed, 3 insertions(+)\n",
+            "",
+            0,
+        );
+        assert!(pulled.changed);
+        // A failure is always worth looking at
+        assert!(summarize(&Command::Status, Vcs::Git, "", "fatal: not a repo\n", 128).changed);
+    }
+}
@@ -0,0 +1,220 @@
+use ratatui::style::{Color, Modifier, Style};
+
+use super::theme::Theme;
+
+/// Split a line into text segments styled by its ANSI SGR sequences.
+/// Other escape sequences (cursor movement, erase, OSC hyperlinks and
+/// titles) are dropped. Styles don't carry over between lines.
+pub fn parse_line(line: &str) -> Vec<(String, Style)> {
+    let mut segments = Vec::new();
+    let mut style = Style::default();
+    let mut text = String::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            if c != '\r' {
+                text.push(c);
+            }
+            continue;
+        }
+        match chars.next() {
+            Some('[') => {}
+            // OSC (hyperlinks, titles), DCS and the other string sequences
+            // run until BEL or ST (ESC \)
+            Some(']' | 'P' | 'X' | '^' | '_') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+                continue;
+            }
+            // ESC, optional intermediate bytes, final byte (e.g. ESC ( B)
+            Some(' '..='/') => {
+                while chars.next_if(|c| (' '..='/').contains(c)).is_some() {}
+                chars.next();
+                continue;
+            }
+            _ => continue,
+        }
+        // CSI: ESC [ params final-byte
+        let mut params = String::new();
+        let mut final_byte = None;
+        for c in chars.by_ref() {
+            if ('\x40'..='\x7e').contains(&c) {
+                final_byte = Some(c);
+                break;
+            }
+            params.push(c);
+        }
+        if final_byte == Some('m') {
+            if !text.is_empty() {
+                segments.push((std::mem::take(&mut text), style));
+            }
+            style = apply_sgr(style, &params);
+        }
+    }
+    if !text.is_empty() {
+        segments.push((text, style));
+    }
+    segments
+}
+
+/// Text of a line without escape sequences
+pub fn strip(line: &str) -> String {
+    if !line.contains('\x1b') {
+        return line.to_string();
+    }
+    parse_line(line).into_iter().map(|(text, _)| text).collect()
+}
+
+fn apply_sgr(mut style: Style, params: &str) -> Style {
+    let codes: Vec<u16> = if params.is_empty() {
+        vec![0]
+    } else {
+        params
+            .split([';', ':'])
+            .map(|p| p.parse().unwrap_or(0))
+            .collect()
+    };
+
+    let mut iter = codes.into_iter();
+    while let Some(code) = iter.next() {
+        style = match code {
+            0 => Style::default(),
+            1 => style.add_modifier(Modifier::BOLD),
+            2 => style.add_modifier(Modifier::DIM),
+            3 => style.add_modifier(Modifier::ITALIC),
+            4 => style.add_modifier(Modifier::UNDERLINED),
+            7 => style.add_modifier(Modifier::REVERSED),
+            9 => style.add_modifier(Modifier::CROSSED_OUT),
+            22 => style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => style.remove_modifier(Modifier::ITALIC),
+            24 => style.remove_modifier(Modifier::UNDERLINED),
+            27 => style.remove_modifier(Modifier::REVERSED),
+            29 => style.remove_modifier(Modifier::CROSSED_OUT),
+            30..=37 => style.fg(basic_color(code - 30, false)),
+            90..=97 => style.fg(basic_color(code - 90, true)),
+            39 => style.fg(Color::Reset),
+            40..=47 => style.bg(basic_color(code - 40, false)),
+            100..=107 => style.bg(basic_color(code - 100, true)),
+            49 => style.bg(Color::Reset),
+            38 | 48 => {
+                let kind = iter.next();
+                // Out-of-range values make the whole color invalid
+                let mut byte = || iter.next().and_then(|n| u8::try_from(n).ok());
+                let color = match kind {
+                    Some(5) => byte().map(Color::Indexed),
+                    Some(2) => match (byte(), byte(), byte()) {
+                        (Some(r), Some(g), Some(b)) => Some(Color::Rgb(r, g, b)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                match color {
+                    Some(c) if code == 38 => style.fg(c),
+                    Some(c) => style.bg(c),
+                    None => style,
+                }
+            }
+            _ => style,
+        };
+    }
+    style
+}
+
+fn basic_color(n: u16, bright: bool) -> Color {
+    match (n, bright) {
+        (0, false) => Color::Black,
+        (1, false) => Color::Red,
+        (2, false) => Color::Green,
+        (3, false) => Color::Yellow,
+        (4, false) => Color::Blue,
+        (5, false) => Color::Magenta,
+        (6, false) => Color::Cyan,
+        (7, false) => Color::Gray,
+        (0, true) => Color::DarkGray,
+        (1, true) => Color::LightRed,
+        (2, true) => Color::LightGreen,
+        (3, true) => Color::LightYellow,
+        (4, true) => Color::LightBlue,
+        (5, true) => Color::LightMagenta,
+        (6, true) => Color::LightCyan,
+        _ => Color::White,
+    }
+}
+
+/// Whether output looks like a unified diff
+pub fn is_diff(content: &str) -> bool {
+    content
+        .lines()
+        .any(|l| l.starts_with("diff ") || l.starts_with("@@ "))
+}
+
+/// Style for a line of a unified diff
+pub fn diff_style(line: &str, theme: &Theme) -> Style {
+    if line.starts_with("diff ")
+        || line.starts_with("index ")
+        || line.starts_with("+++ ")
+        || line.starts_with("--- ")
+    {
+        Style::default().add_modifier(Modifier::BOLD)
+    } else if line.starts_with("@@") {
+        theme.accent
+    } else if line.starts_with('+') {
+        theme.ok
+    } else if line.starts_with('-') {
+        theme.failed
+    } else {
+        Style::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sgr() {
+        let segments = parse_line("\x1b[1;32mok\x1b[0m plain \x1b[38;5;208mx\x1b[K");
+        assert_eq!(
+            segments,
+            vec![
+                (
+                    "ok".to_string(),
+                    Style::default()
+                        .fg(Color::Green)
+                        .add_modifier(Modifier::BOLD)
+                ),
+                (" plain ".to_string(), Style::default()),
+                ("x".to_string(), Style::default().fg(Color::Indexed(208))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_colors_are_ignored() {
+        let style = |params| apply_sgr(Style::default(), params);
+        assert_eq!(style("38;5;300"), Style::default());
+        assert_eq!(style("48;2;10;256;30"), Style::default());
+        assert_eq!(
+            style("38;2;10;20;30"),
+            Style::default().fg(Color::Rgb(10, 20, 30))
+        );
+    }
+
+    #[test]
+    fn test_strip() {
+        assert_eq!(strip("\x1b[31merror\x1b[m: bad"), "error: bad");
+        assert_eq!(strip("no escapes"), "no escapes");
+    }
+
+    #[test]
+    fn test_strip_string_sequences() {
+        let link = "see \x1b]8;;https://example.com\x1b\\docs\x1b]8;;\x1b\\ here";
+        assert_eq!(strip(link), "see docs here");
+        assert_eq!(strip("\x1b]0;title\x07text"), "text");
+        assert_eq!(strip("\x1b(Bplain\x1b=!"), "plain!");
+    }
+}
@@ -0,0 +1,240 @@
+use crossterm::event::KeyCode;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::config::{Settings, SETTINGS_SECTION};
+
+/// Rebindable actions of the repo list and their default keys
+const LIST_ACTIONS: &[(&str, KeyCode)] = &[
+    ("quit", KeyCode::Char('q')),
+    ("up", KeyCode::Char('k')),
+    ("down", KeyCode::Char('j')),
+    ("expand", KeyCode::Enter),
+    ("durations", KeyCode::Char('t')),
+    ("sort", KeyCode::Char('s')),
+    ("group", KeyCode::Char('v')),
+    ("search", KeyCode::Char('/')),
+    ("next", KeyCode::Char('n')),
+    ("prev", KeyCode::Char('N')),
+    ("only_failed", KeyCode::Char('F')),
+    ("only_dirty", KeyCode::Char('D')),
+    ("only_running", KeyCode::Char('R')),
+    ("hide_skipped", KeyCode::Char('H')),
+    ("top", KeyCode::Char('g')),
+    ("bottom", KeyCode::Char('G')),
+    ("split", KeyCode::Char('p')),
+    ("shell", KeyCode::Char('!')),
+    ("editor", KeyCode::Char('e')),
+    ("open", KeyCode::Char('o')),
+    ("mark", KeyCode::Char(' ')),
+    ("mark_failed", KeyCode::Char('f')),
+    ("mark_dirty", KeyCode::Char('d')),
+    ("unmark", KeyCode::Char('u')),
+    ("actions", KeyCode::Char('a')),
+    ("command", KeyCode::Char(':')),
+];
+
+/// Rebindable actions of the expanded output panel and their default keys
+const PANEL_ACTIONS: &[(&str, KeyCode)] = &[
+    ("quit", KeyCode::Char('q')),
+    ("up", KeyCode::Char('k')),
+    ("down", KeyCode::Char('j')),
+    ("page_up", KeyCode::Char('b')),
+    ("page_down", KeyCode::Char(' ')),
+    ("top", KeyCode::Char('g')),
+    ("bottom", KeyCode::Char('G')),
+    ("left", KeyCode::Char('h')),
+    ("right", KeyCode::Char('l')),
+    ("search", KeyCode::Char('/')),
+    ("next", KeyCode::Char('n')),
+    ("prev", KeyCode::Char('N')),
+];
+
+/// Rebindable keys of the action menu, besides its entries' shortcuts
+const MENU_ACTIONS: &[(&str, KeyCode)] = &[
+    ("quit", KeyCode::Char('q')),
+    ("up", KeyCode::Char('k')),
+    ("down", KeyCode::Char('j')),
+];
+
+/// Key remapping from `key.<action> = <key>` settings. Pressed keys are
+/// translated to the action's default key, so the event loop only knows
+/// the defaults; a default key that was moved to another action stops
+/// working. Arrow keys, PgUp/PgDn, Home/End and Esc always keep working.
+#[derive(Debug, Clone, Default)]
+pub struct Keymap {
+    list: HashMap<KeyCode, KeyCode>,
+    panel: HashMap<KeyCode, KeyCode>,
+    menu: HashMap<KeyCode, KeyCode>,
+    /// Key names shown in hints, by action
+    labels: HashMap<&'static str, String>,
+}
+
+impl Keymap {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let mut bound: BTreeMap<&str, (KeyCode, String)> = BTreeMap::new();
+        for (action, name) in &settings.keys {
+            let known = LIST_ACTIONS
+                .iter()
+                .chain(PANEL_ACTIONS)
+                .any(|(a, _)| a == action);
+            if !known {
+                eprintln!(
+                    "warning: [{}] unknown key action: {}",
+                    SETTINGS_SECTION, action
+                );
+                continue;
+            }
+            match parse_key(name) {
+                Some(key) => {
+                    bound.insert(action, (key, name.clone()));
+                }
+                None => eprintln!(
+                    "warning: [{}] invalid key for {}: {}",
+                    SETTINGS_SECTION, action, name
+                ),
+            }
+        }
+
+        let mut labels = HashMap::new();
+        for (action, _) in LIST_ACTIONS.iter().chain(PANEL_ACTIONS) {
+            if let Some((_, name)) = bound.get(action) {
+                labels.insert(*action, name.clone());
+            }
+        }
+        Self {
+            list: translation(LIST_ACTIONS, &bound),
+            panel: translation(PANEL_ACTIONS, &bound),
+            menu: translation(MENU_ACTIONS, &bound),
+            labels,
+        }
+    }
+
+    /// The default key a key pressed in the repo list stands for
+    pub fn list(&self, code: KeyCode) -> KeyCode {
+        self.list.get(&code).copied().unwrap_or(code)
+    }
+
+    /// The default key a key pressed in the output panel stands for
+    pub fn panel(&self, code: KeyCode) -> KeyCode {
+        self.panel.get(&code).copied().unwrap_or(code)
+    }
+
+    /// The default key a key pressed in the action menu stands for
+    pub fn menu(&self, code: KeyCode) -> KeyCode {
+        self.menu.get(&code).copied().unwrap_or(code)
+    }
+
+    /// Key bound to an action, for hints; `default` unless remapped
+    pub fn label<'a>(&'a self, action: &str, default: &'a str) -> &'a str {
+        self.labels.get(action).map_or(default, String::as_str)
+    }
+}
+
+fn translation(
+    actions: &[(&'static str, KeyCode)],
+    bound: &BTreeMap<&str, (KeyCode, String)>,
+) -> HashMap<KeyCode, KeyCode> {
+    let mut map = HashMap::new();
+    let mut owner: HashMap<KeyCode, &str> = HashMap::new();
+    for (action, default) in actions {
+        let Some((key, name)) = bound.get(action) else {
+            continue;
+        };
+        if let Some(other) = owner.insert(*key, action) {
+            eprintln!(
+                "warning: [{}] key {} is bound to both {} and {}",
+                SETTINGS_SECTION, name, other, action
+            );
+        }
+        // The default key no longer does this action, unless another
+        // action is bound to it
+        map.entry(*default).or_insert(KeyCode::Null);
+        map.insert(*key, *default);
+    }
+    map
+}
+
+/// Key from its name in the config: a single character, or `space`,
+/// `semicolon`, `hash`, `enter`, `tab`, `backspace`, `up`, `down`, `left`, `right`, `home`,
+/// `end`, `pageup` or `pagedown`
+fn parse_key(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+    Some(match name.to_ascii_lowercase().as_str() {
+        "space" => KeyCode::Char(' '),
+        // `;` and `#` start comments in the config
+        "semicolon" => KeyCode::Char(';'),
+        "hash" => KeyCode::Char('#'),
+        "enter" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" | "pgup" => KeyCode::PageUp,
+        "pagedown" | "pgdn" => KeyCode::PageDown,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keymap(keys: &[(&str, &str)]) -> Keymap {
+        let settings = Settings {
+            keys: keys
+                .iter()
+                .map(|(a, k)| (a.to_string(), k.to_string()))
+                .collect(),
+            ..Default::default()
+        };
+        Keymap::from_settings(&settings)
+    }
+
+    #[test]
+    fn test_swap_keys() {
+        let keys = keymap(&[("down", "n"), ("next", "j")]);
+        assert_eq!(keys.list(KeyCode::Char('n')), KeyCode::Char('j'));
+        assert_eq!(keys.list(KeyCode::Char('j')), KeyCode::Char('n'));
+        assert_eq!(keys.panel(KeyCode::Char('n')), KeyCode::Char('j'));
+        assert_eq!(keys.list(KeyCode::Char('q')), KeyCode::Char('q'));
+        assert_eq!(keys.label("down", "j"), "n");
+    }
+
+    #[test]
+    fn test_menu_keys_follow_bindings() {
+        let keys = keymap(&[("down", "n"), ("quit", "x")]);
+        assert_eq!(keys.menu(KeyCode::Char('n')), KeyCode::Char('j'));
+        assert_eq!(keys.menu(KeyCode::Char('j')), KeyCode::Null);
+        assert_eq!(keys.menu(KeyCode::Char('x')), KeyCode::Char('q'));
+        // Entry shortcuts are left alone
+        assert_eq!(keys.menu(KeyCode::Char('u')), KeyCode::Char('u'));
+    }
+
+    #[test]
+    fn test_moved_default_key_is_unbound() {
+        let keys = keymap(&[("mark", "m")]);
+        assert_eq!(keys.list(KeyCode::Char('m')), KeyCode::Char(' '));
+        assert_eq!(keys.list(KeyCode::Char(' ')), KeyCode::Null);
+        // Space still pages in the panel
+        assert_eq!(keys.panel(KeyCode::Char(' ')), KeyCode::Char(' '));
+    }
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key("Q"), Some(KeyCode::Char('Q')));
+        assert_eq!(parse_key("space"), Some(KeyCode::Char(' ')));
+        assert_eq!(parse_key("Semicolon"), Some(KeyCode::Char(';')));
+        assert_eq!(parse_key("hash"), Some(KeyCode::Char('#')));
+        assert_eq!(parse_key("PgDn"), Some(KeyCode::PageDown));
+        assert_eq!(parse_key("enter"), Some(KeyCode::Enter));
+        assert_eq!(parse_key("ctrl-x"), None);
+        assert_eq!(parse_key(""), None);
+    }
+}
@@ -1,22 +1,28 @@
+pub mod ansi;
 pub mod event;
+pub mod keys;
 pub mod render;
 pub mod spinner;
 pub mod state;
+pub mod theme;
 
+use clap::Parser;
 use crossterm::{
     event::KeyCode,
     execute,
     terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::prelude::*;
-use state::{AppState, RepoStatus};
+use state::{Action, AppState, Only, RepoStatus, MENU};
 use std::io::{self, stdout};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
-use crate::cli::Command;
-use crate::config::Repo;
-use crate::executor::TaskEvent;
+use crate::cli::{Command, FetchOpts, Prompt, WorktreeAction};
+use crate::config::{Repo, Settings};
+use crate::executor::{self, TaskEvent};
+use crate::operations;
+use crate::shellwords;
 use crate::summarize;
 
 pub fn install_panic_hook() {
@@ -28,11 +34,336 @@ pub fn install_panic_hook() {
     }));
 }
 
+/// How `run` behaves beyond showing results
+pub struct Options {
+    /// Job limits of the initial command, shared with commands started
+    /// from the TUI
+    pub limits: executor::Limits,
+    /// Quit once every repo has finished
+    pub exit_when_done: bool,
+    /// List changed repos, not just failed ones, in the exit report
+    pub report_changed: bool,
+    /// Run the command again this long after each run finishes
+    pub watch: Option<Duration>,
+    /// Command line of the initial command, recorded in run history
+    pub args: Vec<String>,
+}
+
 pub fn run(
     repos: Vec<Repo>,
     command: &Command,
-    mut rx: mpsc::UnboundedReceiver<TaskEvent>,
-) -> io::Result<bool> {
+    rx: mpsc::UnboundedReceiver<TaskEvent>,
+    options: &Options,
+    settings: &Settings,
+) -> io::Result<AppState> {
+    let limits = &options.limits;
+    let mut state = AppState::new(repos, command.display_name());
+    state.exit_when_done = options.exit_when_done;
+    state.watch = options.watch;
+    state.command_args = options.args.clone();
+    let mut current = command.clone();
+    let mut sources = vec![Source {
+        rx,
+        indices: (0..state.total()).collect(),
+        remaining: state.total(),
+        command: command.clone(),
+    }];
+    let state = event_loop(state, settings, |state| {
+        for line in std::mem::take(&mut state.commands) {
+            let command = match parse_prompt(&line) {
+                Ok(command) => command,
+                Err(e) => {
+                    state.message = Some(e);
+                    continue;
+                }
+            };
+            let targets = prompt_targets(state, &command);
+            if targets.iter().any(|&i| !state.statuses[i].is_done()) {
+                state.message = Some("wait for running repos to finish".into());
+                continue;
+            }
+            state.command_name = command.display_name().to_string();
+            state.command_args = prompt_args(&line);
+            state.subtitle = None;
+            state.created_at = Instant::now();
+            state.finished_at = None;
+            current = command.clone();
+            sources.push(start(state, command, targets, limits, true));
+        }
+
+        let rerun = std::mem::take(&mut state.rerun);
+        let refresh = settings.refresh_after_open() && current.is_read_only();
+        if refresh && !rerun.is_empty() {
+            sources.push(start(state, current.clone(), rerun, limits, true));
+        }
+        for (action, targets) in std::mem::take(&mut state.actions) {
+            let command = action_command(action);
+            sources.push(start(state, command, targets, limits, true));
+        }
+
+        // Watch mode: start over once everything has been idle for the interval
+        if let Some(interval) = state.watch {
+            let idle = sources.iter().all(|s| s.remaining == 0);
+            match state.next_cycle {
+                None if idle => state.next_cycle = Some(Instant::now() + interval),
+                Some(at) if idle && Instant::now() >= at => {
+                    state.next_cycle = None;
+                    state.previous = state.statuses.clone();
+                    let all = (0..state.total()).collect();
+                    sources.push(start(state, current.clone(), all, limits, false));
+                }
+                _ => {}
+            }
+        }
+
+        // Drain pending events from executor
+        for source in &mut sources {
+            while let Ok(mut evt) = source.rx.try_recv() {
+                let index = evt.index_mut();
+                *index = source.indices[*index];
+                if matches!(evt, TaskEvent::Finished { .. } | TaskEvent::Skipped { .. }) {
+                    source.remaining -= 1;
+                }
+                apply_event(state, &evt, &source.command);
+            }
+        }
+        sources.retain(|s| s.remaining > 0);
+    })?;
+
+    // Print final summary
+    let failed = state.failed_count();
+    let done = state.done_count();
+    let total = state.total();
+    let timing = match state.wall_time() {
+        Some(wall) => format!(
+            " in {} (cumulative {})",
+            render::format_duration(wall),
+            render::format_duration(state.durations.iter().flatten().sum())
+        ),
+        None => String::new(),
+    };
+    if failed > 0 {
+        eprintln!(
+            "mrx {}: {}/{} done, {} failed{}",
+            state.command_name, done, total, failed, timing
+        );
+    } else {
+        eprintln!(
+            "mrx {}: {}/{} done{}",
+            state.command_name, done, total, timing
+        );
+    }
+    print_report(&state, Only::Failed);
+    if options.report_changed {
+        print_report(&state, Only::Dirty);
+    }
+    print_slowest(&state);
+
+    Ok(state)
+}
+
+/// Events of the initial run or of a follow-up action on some repos
+struct Source {
+    rx: mpsc::UnboundedReceiver<TaskEvent>,
+    /// Repo index for each of the source's task indices
+    indices: Vec<usize>,
+    /// Tasks that haven't finished or been skipped yet
+    remaining: usize,
+    command: Command,
+}
+
+/// Run `command` for the repos at `indices`. With `reset`, their rows go
+/// back to pending; otherwise they keep showing the last result until the
+/// new one arrives.
+fn start(
+    state: &mut AppState,
+    command: Command,
+    indices: Vec<usize>,
+    limits: &executor::Limits,
+    reset: bool,
+) -> Source {
+    let repos: Vec<Repo> = indices.iter().map(|&i| state.repos[i].clone()).collect();
+    let ops = repos
+        .iter()
+        .map(|r| operations::plan(&command, r))
+        .collect();
+    if reset {
+        for &i in &indices {
+            state.statuses[i] = RepoStatus::Pending;
+            state.durations[i] = None;
+            state.started_at[i] = None;
+        }
+    }
+    Source {
+        rx: executor::execute_all(&repos, ops, limits),
+        remaining: indices.len(),
+        indices,
+        command,
+    }
+}
+
+/// Parse a line typed at the `:` prompt. `run` takes the rest of the line
+/// as its shell command; anything else is split into words like a shell
+/// command line.
+fn parse_prompt(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+    if let Some(cmd) = line.strip_prefix("run ") {
+        return Ok(Command::Run {
+            cmd: vec![cmd.trim().to_string()],
+        });
+    }
+    let mut commands = shellwords::split_commands(line)?;
+    if commands.len() != 1 {
+        return Err("enter a single mrx command".into());
+    }
+    let command = match Prompt::try_parse_from(commands.remove(0)) {
+        Ok(prompt) => prompt.command,
+        Err(e) => {
+            // First line of clap's message, without its "error: " prefix
+            let text = e.render().to_string();
+            let first = text.lines().next().unwrap_or_default();
+            return Err(first.trim_start_matches("error: ").to_string());
+        }
+    };
+    if !command.is_operation() {
+        return Err(format!(
+            "{} can't run from the prompt",
+            command.display_name()
+        ));
+    }
+    Ok(command)
+}
+
+/// Words of a line accepted by `parse_prompt`, split the same way
+fn prompt_args(line: &str) -> Vec<String> {
+    let line = line.trim();
+    match line.strip_prefix("run ") {
+        Some(cmd) => vec!["run".into(), cmd.trim().to_string()],
+        None => shellwords::split_commands(line)
+            .unwrap_or_default()
+            .concat(),
+    }
+}
+
+/// Repos a prompt command runs in: the marked ones, else all of them.
+/// `worktree add` with repo names only runs in those.
+fn prompt_targets(state: &AppState, command: &Command) -> Vec<usize> {
+    if let Command::Worktree {
+        action: WorktreeAction::Add { repos: names, .. },
+    } = command
+    {
+        if !names.is_empty() {
+            return (0..state.total())
+                .filter(|&i| {
+                    let repo = &state.repos[i];
+                    names.contains(&repo.name) || names.contains(&repo.section)
+                })
+                .collect();
+        }
+    }
+    if state.marked.is_empty() {
+        (0..state.total()).collect()
+    } else {
+        state.marked.iter().copied().collect()
+    }
+}
+
+fn action_command(action: Action) -> Command {
+    match action {
+        Action::Pull => Command::Update {
+            fetch: FetchOpts::default(),
+        },
+        Action::Push => Command::Push,
+        Action::Status => Command::Status,
+        Action::Run(cmd) => Command::Run { cmd: vec![cmd] },
+    }
+}
+
+/// Columns moved per horizontal scroll in the expanded panel
+const H_SCROLL_STEP: usize = 8;
+
+/// How many repos the "slowest repos" report lists
+const SLOWEST_COUNT: usize = 5;
+
+/// List the repos of one kind with their summaries, so they stay visible
+/// after leaving the alternate screen
+fn print_report(state: &AppState, only: Only) {
+    let listed: Vec<usize> = (0..state.total())
+        .filter(|&i| state.matches_only(i, only))
+        .collect();
+    if listed.is_empty() {
+        return;
+    }
+    let width = listed
+        .iter()
+        .map(|&i| state.repos[i].name.len())
+        .max()
+        .unwrap_or(0);
+    eprintln!("{} repos:", only.label());
+    for i in listed {
+        let summary = match &state.statuses[i] {
+            RepoStatus::Done { summary, .. } => ansi::strip(summary),
+            _ => String::new(),
+        };
+        eprintln!(
+            "  {:width$}  {}",
+            state.repos[i].name,
+            summary,
+            width = width
+        );
+    }
+}
+
+fn print_slowest(state: &AppState) {
+    let mut timed: Vec<(&str, Duration)> = state
+        .repos
+        .iter()
+        .zip(&state.durations)
+        .filter_map(|(repo, d)| Some((repo.name.as_str(), (*d)?)))
+        .collect();
+    if timed.len() < 2 {
+        return;
+    }
+    timed.sort_by_key(|(_, d)| std::cmp::Reverse(*d));
+    let width = timed
+        .iter()
+        .take(SLOWEST_COUNT)
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    eprintln!("slowest repos:");
+    for (name, d) in timed.iter().take(SLOWEST_COUNT) {
+        eprintln!(
+            "  {:width$}  {:>7}",
+            name,
+            render::format_duration(*d),
+            width = width
+        );
+    }
+}
+
+/// Browse a finished run's results without executing anything
+pub fn replay(state: AppState, settings: &Settings) -> io::Result<()> {
+    event_loop(state, settings, |state| {
+        state.rerun.clear();
+        if !state.actions.is_empty() || !state.commands.is_empty() {
+            state.actions.clear();
+            state.commands.clear();
+            state.message = Some("commands can't run on a recorded run".into());
+        }
+    })?;
+    Ok(())
+}
+
+/// Draw and handle keys until the user quits, calling `update` each frame
+fn event_loop(
+    mut state: AppState,
+    settings: &Settings,
+    mut update: impl FnMut(&mut AppState),
+) -> io::Result<AppState> {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    state.theme = theme::Theme::from_settings(settings, no_color);
+    state.keymap = keys::Keymap::from_settings(settings);
     install_panic_hook();
 
     terminal::enable_raw_mode()?;
@@ -41,24 +372,25 @@ pub fn run(
     let backend = CrosstermBackend::new(stdout());
     let mut terminal = Terminal::new(backend)?;
 
-    let mut state = AppState::new(repos, command.display_name());
-    let mut all_succeeded = true;
-
     loop {
-        // Drain pending events from executor
-        loop {
-            match rx.try_recv() {
-                Ok(evt) => apply_event(&mut state, &evt, command),
-                Err(_) => break,
-            }
-        }
+        update(&mut state);
+        state.refresh_rows();
 
         // Check if all done
         state.all_done = state.done_count() == state.total();
+        if state.all_done && state.finished_at.is_none() {
+            state.finished_at = Some(Instant::now());
+        }
 
         // Render
         terminal.draw(|frame| render::draw(frame, &state))?;
 
+        let queued =
+            !state.rerun.is_empty() || !state.actions.is_empty() || !state.commands.is_empty();
+        if state.exit_when_done && state.all_done && !queued {
+            break;
+        }
+
         // Handle input
         if let Some(app_event) = event::poll(Duration::from_millis(80)) {
             match app_event {
@@ -69,32 +401,181 @@ pub fn run(
                     {
                         break;
                     }
+                    state.message = None;
 
-                    if state.expanded.is_some() {
-                        // Expanded mode keys
+                    if state.searching {
+                        // Search input keys
+                        match code {
+                            KeyCode::Esc => {
+                                state.searching = false;
+                                state.search.clear();
+                            }
+                            KeyCode::Enter => state.searching = false,
+                            KeyCode::Backspace => {
+                                state.search.pop();
+                                state.search_jump(true, true);
+                            }
+                            KeyCode::Char(c) => {
+                                state.search.push(c);
+                                state.search_jump(true, true);
+                            }
+                            _ => {}
+                        }
+                    } else if state.panel_searching {
+                        // Search input within the expanded panel
+                        match code {
+                            KeyCode::Esc => {
+                                state.panel_searching = false;
+                                state.panel_search.clear();
+                            }
+                            KeyCode::Enter => state.panel_searching = false,
+                            KeyCode::Backspace => {
+                                state.panel_search.pop();
+                                state.panel_search_jump(true, true);
+                            }
+                            KeyCode::Char(c) => {
+                                state.panel_search.push(c);
+                                state.panel_search_jump(true, true);
+                            }
+                            _ => {}
+                        }
+                    } else if let Some(input) = &mut state.prompt {
+                        // `:` command prompt
+                        match code {
+                            KeyCode::Esc => state.prompt = None,
+                            KeyCode::Enter => {
+                                let line = input.trim().to_string();
+                                state.prompt = None;
+                                if !line.is_empty() {
+                                    state.commands.push(line);
+                                }
+                            }
+                            KeyCode::Backspace => {
+                                input.pop();
+                            }
+                            KeyCode::Char(c) => input.push(c),
+                            _ => {}
+                        }
+                    } else if let Some(input) = &mut state.run_input {
+                        // Command for the "run" action
                         match code {
+                            KeyCode::Esc => state.run_input = None,
+                            KeyCode::Enter => {
+                                let cmd = input.trim().to_string();
+                                state.run_input = None;
+                                if !cmd.is_empty() {
+                                    state.dispatch(Action::Run(cmd));
+                                }
+                            }
+                            KeyCode::Backspace => {
+                                input.pop();
+                            }
+                            KeyCode::Char(c) => input.push(c),
+                            _ => {}
+                        }
+                    } else if let Some(item) = state.menu {
+                        // Action menu keys
+                        let chosen = match state.keymap.menu(code) {
+                            KeyCode::Esc | KeyCode::Char('q') => {
+                                state.menu = None;
+                                None
+                            }
+                            KeyCode::Up | KeyCode::Char('k') => {
+                                state.menu = Some(item.saturating_sub(1));
+                                None
+                            }
+                            KeyCode::Down | KeyCode::Char('j') => {
+                                state.menu = Some((item + 1).min(MENU.len() - 1));
+                                None
+                            }
+                            KeyCode::Enter => Some(MENU[item].0),
+                            KeyCode::Char(c) => MENU.iter().find(|(k, _)| *k == c).map(|(k, _)| *k),
+                            _ => None,
+                        };
+                        if let Some(key) = chosen {
+                            state.menu = None;
+                            match key {
+                                'u' => state.dispatch(Action::Pull),
+                                'p' => state.dispatch(Action::Push),
+                                's' => state.dispatch(Action::Status),
+                                _ => state.run_input = Some(String::new()),
+                            }
+                        }
+                    } else if state.expanded.is_some() {
+                        // Expanded mode keys
+                        let max = state
+                            .expanded_content()
+                            .map(|c| c.lines().count())
+                            .unwrap_or(0);
+                        let size = terminal.size()?;
+                        let page = render::panel_height(&state, size.width, size.height);
+                        match state.keymap.panel(code) {
+                            KeyCode::Esc if !state.panel_search.is_empty() => {
+                                state.panel_search.clear()
+                            }
                             KeyCode::Esc | KeyCode::Enter => state.collapse(),
                             KeyCode::Up | KeyCode::Char('k') => state.scroll_up(),
-                            KeyCode::Down | KeyCode::Char('j') => {
-                                let max = state
-                                    .expanded_content()
-                                    .map(|c| c.lines().count())
-                                    .unwrap_or(0);
-                                state.scroll_down(max);
+                            KeyCode::Down | KeyCode::Char('j') => state.scroll_down(max),
+                            KeyCode::PageUp | KeyCode::Char('b') => {
+                                state.scroll_by(-(page as isize), max)
+                            }
+                            KeyCode::PageDown | KeyCode::Char(' ') => {
+                                state.scroll_by(page as isize, max)
+                            }
+                            KeyCode::Home | KeyCode::Char('g') => state.scroll_offset = 0,
+                            KeyCode::End | KeyCode::Char('G') => state.scroll_to_bottom(page, max),
+                            KeyCode::Left | KeyCode::Char('h') => state.scroll_left(H_SCROLL_STEP),
+                            KeyCode::Right | KeyCode::Char('l') => {
+                                state.scroll_right(H_SCROLL_STEP, page)
+                            }
+                            KeyCode::Char('/') => {
+                                state.panel_search.clear();
+                                state.panel_searching = true;
                             }
+                            KeyCode::Char('n') => state.panel_search_jump(true, false),
+                            KeyCode::Char('N') => state.panel_search_jump(false, false),
                             KeyCode::Char('q') => break,
                             _ => {}
                         }
                     } else {
                         // Normal mode keys
-                        match code {
+                        match state.keymap.list(code) {
                             KeyCode::Char('q') => break,
                             KeyCode::Up | KeyCode::Char('k') => state.move_up(),
                             KeyCode::Down | KeyCode::Char('j') => state.move_down(),
                             KeyCode::Enter => state.toggle_expand(),
+                            KeyCode::Char('t') => state.show_durations = !state.show_durations,
+                            KeyCode::Char('p') => state.split = !state.split,
+                            KeyCode::Char(' ') => state.toggle_mark(),
+                            KeyCode::Char('f') => state.mark_all(Only::Failed),
+                            KeyCode::Char('d') => state.mark_all(Only::Dirty),
+                            KeyCode::Char('u') => state.marked.clear(),
+                            KeyCode::Char('a') => state.menu = Some(0),
+                            KeyCode::Char(':') => state.prompt = Some(String::new()),
+                            KeyCode::Char(c @ ('!' | 'e' | 'o')) => {
+                                if let Some(i) = state.selected_repo() {
+                                    let cmd = launch_command(c, settings, |name| {
+                                        std::env::var(name).ok()
+                                    });
+                                    open_in_repo(&mut terminal, &mut state, i, &cmd)?;
+                                }
+                            }
+                            KeyCode::Char('s') => state.cycle_sort(),
+                            KeyCode::Char('v') => state.cycle_group(),
+                            KeyCode::Char('/') => {
+                                state.search.clear();
+                                state.searching = true;
+                            }
+                            KeyCode::Char('n') => state.search_jump(true, false),
+                            KeyCode::Char('N') => state.search_jump(false, false),
+                            KeyCode::Char('F') => state.toggle_only(Only::Failed),
+                            KeyCode::Char('D') => state.toggle_only(Only::Dirty),
+                            KeyCode::Char('R') => state.toggle_only(Only::Running),
+                            KeyCode::Char('H') => state.toggle_hide_skipped(),
+                            KeyCode::Esc => state.clear_filters(),
                             KeyCode::Home | KeyCode::Char('g') => state.selected = 0,
                             KeyCode::End | KeyCode::Char('G') => {
-                                state.selected = state.total().saturating_sub(1)
+                                state.selected = state.rows.len().saturating_sub(1)
                             }
                             _ => {}
                         }
@@ -111,37 +592,98 @@ pub fn run(
     terminal::disable_raw_mode()?;
     execute!(stdout(), LeaveAlternateScreen)?;
 
-    // Print final summary
-    let failed = state.failed_count();
-    let done = state.done_count();
-    let total = state.total();
-    if failed > 0 {
-        eprintln!(
-            "mrx {}: {}/{} done, {} failed",
-            state.command_name, done, total, failed
-        );
-        all_succeeded = false;
-    } else {
-        eprintln!("mrx {}: {}/{} done", state.command_name, done, total);
+    Ok(state)
+}
+
+/// Shell command line for one of the open keys: `!` starts a shell, `e`
+/// an editor on the repo and `o` the configured `open_command`
+fn launch_command(key: char, settings: &Settings, env: impl Fn(&str) -> Option<String>) -> String {
+    match key {
+        '!' => env("SHELL").unwrap_or_else(|| "sh".into()),
+        'e' => {
+            let editor = env("VISUAL")
+                .or_else(|| env("EDITOR"))
+                .unwrap_or_else(|| "vi".into());
+            format!("{} .", editor)
+        }
+        _ => settings.open_command().to_string(),
+    }
+}
+
+/// Suspend the TUI, run `cmd` in repo `i` until it exits, then restore the
+/// TUI and queue the repo to run again
+fn open_in_repo(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    state: &mut AppState,
+    i: usize,
+    cmd: &str,
+) -> io::Result<()> {
+    let path = &state.repos[i].path;
+    if !path.is_dir() {
+        state.message = Some(format!("{} is not checked out", state.repos[i].name));
+        return Ok(());
     }
 
-    Ok(all_succeeded)
+    let suspended =
+        terminal::disable_raw_mode().and_then(|_| execute!(stdout(), LeaveAlternateScreen));
+    let status = suspended.and_then(|_| {
+        std::process::Command::new("sh")
+            .args(["-c", cmd])
+            .current_dir(path)
+            .status()
+    });
+
+    // Restore the TUI even if suspending it failed halfway
+    let restored = terminal::enable_raw_mode()
+        .and_then(|_| execute!(stdout(), EnterAlternateScreen))
+        .and_then(|_| terminal.clear());
+    if let Err(e) = restored {
+        // The TUI quits on this error; leave a usable terminal behind
+        let _ = terminal::disable_raw_mode();
+        let _ = execute!(stdout(), LeaveAlternateScreen);
+        return Err(e);
+    }
+
+    match status {
+        Ok(s) if s.success() => {}
+        Ok(s) => {
+            state.message = Some(match s.code() {
+                Some(code) => format!("{} exited with status {}", cmd, code),
+                None => format!("{} was killed", cmd),
+            })
+        }
+        Err(e) => state.message = Some(format!("cannot run {}: {}", cmd, e)),
+    }
+    state.queue_rerun(i);
+    Ok(())
 }
 
 fn apply_event(state: &mut AppState, event: &TaskEvent, command: &Command) {
     match event {
+        TaskEvent::Blocked { index, on } => {
+            state.statuses[*index] = if on.is_empty() {
+                RepoStatus::Pending
+            } else {
+                RepoStatus::Blocked { on: on.clone() }
+            };
+        }
         TaskEvent::Started { index } => {
             state.statuses[*index] = RepoStatus::Running;
+            state.started_at[*index] = Some(Instant::now());
         }
         TaskEvent::Finished {
             index,
             stdout,
             stderr,
             exit_code,
+            duration,
         } => {
-            let summary = summarize::summarize(command, stdout, stderr, *exit_code);
+            state.durations[*index] = Some(*duration);
+            let vcs = state.repos[*index].vcs;
+            let summary = summarize::summarize(command, vcs, stdout, stderr, *exit_code);
             state.statuses[*index] = RepoStatus::Done {
-                summary,
+                summary: summary.text,
+                changed: summary.changed,
                 stdout: stdout.clone(),
                 stderr: stderr.clone(),
                 exit_code: *exit_code,
@@ -154,3 +696,57 @@ fn apply_event(state: &mut AppState, event: &TaskEvent, command: &Command) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_prompt() {
+        match parse_prompt("run git log -1 | head -n 1") {
+            Ok(Command::Run { cmd }) => assert_eq!(cmd, vec!["git log -1 | head -n 1"]),
+            _ => panic!("expected run"),
+        }
+        match parse_prompt("fetch --from-mirror") {
+            Ok(Command::Fetch { fetch }) => assert!(fetch.from_mirror),
+            _ => panic!("expected fetch"),
+        }
+        assert!(matches!(parse_prompt(" status "), Ok(Command::Status)));
+        assert!(parse_prompt("list").is_err());
+        assert!(parse_prompt("bogus").is_err());
+        assert!(parse_prompt("status; diff").is_err());
+    }
+
+    #[test]
+    fn test_prompt_args() {
+        assert_eq!(
+            prompt_args("run git log -1 | head"),
+            vec!["run", "git log -1 | head"]
+        );
+        assert_eq!(
+            prompt_args(" update --from-mirror "),
+            vec!["update", "--from-mirror"]
+        );
+    }
+
+    #[test]
+    fn test_launch_command() {
+        let settings = Settings {
+            open_command: Some("tig".into()),
+            ..Default::default()
+        };
+        let env = |name: &str| match name {
+            "SHELL" => Some("/bin/zsh".to_string()),
+            "EDITOR" => Some("nano".to_string()),
+            _ => None,
+        };
+        assert_eq!(launch_command('!', &settings, env), "/bin/zsh");
+        assert_eq!(launch_command('e', &settings, env), "nano .");
+        assert_eq!(launch_command('o', &settings, env), "tig");
+
+        let empty = |_: &str| None;
+        assert_eq!(launch_command('!', &settings, empty), "sh");
+        assert_eq!(launch_command('e', &settings, empty), "vi .");
+        assert_eq!(launch_command('o', &Settings::default(), empty), "lazygit");
+    }
+}
@@ -1,79 +1,334 @@
 use ratatui::prelude::*;
-use ratatui::widgets::Paragraph;
+use ratatui::widgets::{Block, Clear, Paragraph};
+use std::time::{Duration, Instant};
 
+use super::ansi;
 use super::spinner;
-use super::state::{AppState, RepoStatus};
+use super::state::{AppState, GroupMode, RepoStatus, Row, SortMode, MENU};
+use super::theme::Theme;
+
+/// Narrowest terminal that gets the split view; narrower ones fall back
+/// to the inline panel
+const MIN_SPLIT_WIDTH: u16 = 100;
 
 pub fn draw(frame: &mut Frame, state: &AppState) {
     let area = frame.area();
-
-    let max_name_len = state.repos.iter().map(|r| r.name.len()).max().unwrap_or(10);
+    let split = is_split(state, area.width);
 
     // Calculate visible area for repo list
     let list_height = area.height.saturating_sub(4) as usize; // header + 2 separators + footer
 
-    // Determine scroll window
-    let (view_start, expanded_rows) = calculate_scroll(state, list_height);
+    let header = header_lines(state, area.width);
+    let footer = footer_lines(state, area.width);
 
-    let mut lines: Vec<Line> = Vec::new();
+    if split {
+        let [top, body, bottom] = Layout::vertical([
+            Constraint::Length(2),
+            Constraint::Min(0),
+            Constraint::Length(2),
+        ])
+        .areas(area);
+        let [left, right] =
+            Layout::horizontal([Constraint::Length(area.width * 2 / 5), Constraint::Min(0)])
+                .areas(body);
+        frame.render_widget(Paragraph::new(header), top);
+        frame.render_widget(
+            Paragraph::new(repo_lines(state, list_height, area, false)),
+            left,
+        );
+        draw_pane(frame, state, right);
+        frame.render_widget(Paragraph::new(footer), bottom);
+    } else {
+        let mut lines = header;
+        lines.extend(repo_lines(state, list_height, area, true));
+        lines.extend(footer);
+        frame.render_widget(Paragraph::new(lines), area);
+    }
 
-    // Header
-    let summary = state.summary_line();
-    let title = format!("  mrx {}", state.command_name);
-    let gap = (area.width as usize).saturating_sub(title.len() + summary.len());
-    lines.push(Line::from(vec![
-        Span::styled(&title, Style::default().bold()),
-        Span::raw(" ".repeat(gap)),
-        Span::styled(&summary, Style::default().fg(Color::DarkGray)),
-    ]));
+    if let Some(item) = state.menu {
+        draw_menu(frame, state, item);
+    }
+}
+
+/// Popup listing the follow-up actions for the marked repos
+fn draw_menu(frame: &mut Frame, state: &AppState, item: usize) {
+    let targets = state.action_targets().len();
+    let title = format!(
+        " run on {} repo{} ",
+        targets,
+        if targets == 1 { "" } else { "s" }
+    );
+    let theme = &state.theme;
+    let lines: Vec<Line> = MENU
+        .iter()
+        .enumerate()
+        .map(|(n, (key, label))| {
+            let style = if n == item {
+                theme.highlight
+            } else {
+                Style::default()
+            };
+            Line::from(vec![
+                Span::styled(format!(" {} ", key), theme.accent.bold()),
+                Span::styled(format!(" {:<20}", label), style),
+            ])
+        })
+        .collect();
 
-    // Separator
-    lines.push(Line::from(Span::styled(
-        "─".repeat(area.width as usize),
-        Style::default().fg(Color::DarkGray),
-    )));
+    let area = frame.area();
+    let width = 28.min(area.width);
+    let height = (MENU.len() as u16 + 2).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    let block = Block::bordered()
+        .border_set(theme.symbols.border)
+        .title(title)
+        .border_style(theme.accent);
+    frame.render_widget(Clear, popup);
+    frame.render_widget(Paragraph::new(lines).block(block), popup);
+}
+
+/// Whether the split view is on and the terminal is wide enough for it
+fn is_split(state: &AppState, width: u16) -> bool {
+    state.split && width >= MIN_SPLIT_WIDTH
+}
 
-    // Repo rows
+/// Title with the summary on the right, and a separator
+fn header_lines<'a>(state: &AppState, width: u16) -> Vec<Line<'a>> {
+    let mut summary = state.summary_line();
+    if state.group_by != GroupMode::None {
+        summary = format!("by {}  {}", state.group_by.label(), summary);
+    }
+    let mut filters: Vec<&str> = state.only.iter().map(|o| o.label()).collect();
+    if state.hide_skipped {
+        filters.push("no skipped");
+    }
+    if !filters.is_empty() {
+        summary = format!(
+            "{} ({} hidden)  {}",
+            filters.join(", "),
+            state.hidden_count(),
+            summary
+        );
+    }
+    if state.sort != SortMode::Config {
+        summary = format!("sorted by {}  {}", state.sort.label(), summary);
+    }
+    if !state.marked.is_empty() {
+        summary = format!("{} marked  {}", state.marked.len(), summary);
+    }
+    if let Some(interval) = state.watch {
+        let next = match state.next_cycle {
+            Some(at) => {
+                let left = at.saturating_duration_since(Instant::now());
+                format!(", next in {}s", left.as_secs() + 1)
+            }
+            None => String::new(),
+        };
+        summary = format!("every {}{}  {}", format_interval(interval), next, summary);
+    }
+    let title = match &state.subtitle {
+        Some(sub) => format!("  mrx {}  ({})", state.command_name, sub),
+        None => format!("  mrx {}", state.command_name),
+    };
+    let gap = (width as usize).saturating_sub(title.len() + summary.len());
+    vec![
+        Line::from(vec![
+            Span::styled(title, Style::default().bold()),
+            Span::raw(" ".repeat(gap)),
+            Span::styled(summary, state.theme.dim),
+        ]),
+        separator(state, width),
+    ]
+}
+
+fn separator<'a>(state: &AppState, width: u16) -> Line<'a> {
+    Line::from(Span::styled(
+        state.theme.symbols.separator.repeat(width as usize),
+        state.theme.dim,
+    ))
+}
+
+/// Separator and key hints
+fn footer_lines<'a>(state: &AppState, width: u16) -> Vec<Line<'a>> {
+    let cursor = state.theme.symbols.cursor;
+    let key = |action, default| state.keymap.label(action, default);
+    let footer = if let Some(message) = &state.message {
+        format!("  {}", message)
+    } else if let Some(input) = &state.prompt {
+        format!("  :{}{}", input, cursor)
+    } else if let Some(input) = &state.run_input {
+        format!("  run: {}{}", input, cursor)
+    } else if state.menu.is_some() {
+        format!(
+            "  [↑↓/{}{}] choose  [enter] run  [esc/{}] cancel",
+            key("down", "j"),
+            key("up", "k"),
+            key("quit", "q"),
+        )
+    } else if state.searching {
+        format!("  /{}{}", state.search, cursor)
+    } else if state.panel_searching {
+        format!("  /{}{}", state.panel_search, cursor)
+    } else if state.expanded.is_some() {
+        let back = if is_split(state, width) {
+            "unfocus"
+        } else {
+            "collapse"
+        };
+        format!(
+            "  [↑↓] scroll  [pgup/pgdn] page  [{}/{}] top/bottom  [←→] pan  [{}] search  [esc] {}  [{}] quit",
+            key("top", "g"),
+            key("bottom", "G"),
+            key("search", "/"),
+            back,
+            key("quit", "q"),
+        )
+    } else if state.rows.is_empty() && state.hidden_count() > 0 {
+        "  all repos hidden by filters  [esc] clear".into()
+    } else {
+        let (expand, split) = if is_split(state, width) {
+            ("focus output", "inline")
+        } else {
+            ("expand", "split")
+        };
+        format!(
+            "  [↑↓/{}{}] navigate  [{}] {}  [{}] {}  [{}/{}/{}] shell/editor/open  [{}] mark  [{}] actions  [{}] command  [{}] search  [{}] sort  [{}] group  [{}/{}/{}/{}] filter  [{}] quit",
+            key("down", "j"),
+            key("up", "k"),
+            key("expand", "enter"),
+            expand,
+            key("split", "p"),
+            split,
+            key("shell", "!"),
+            key("editor", "e"),
+            key("open", "o"),
+            key("mark", "space"),
+            key("actions", "a"),
+            key("command", ":"),
+            key("search", "/"),
+            key("sort", "s"),
+            key("group", "v"),
+            key("only_failed", "F"),
+            key("only_dirty", "D"),
+            key("only_running", "R"),
+            key("hide_skipped", "H"),
+            key("quit", "q"),
+        )
+    };
+    let footer_style = if state.message.is_some()
+        || state.prompt.is_some()
+        || state.run_input.is_some()
+        || state.searching
+        || state.panel_searching
+    {
+        state.theme.search
+    } else {
+        state.theme.dim
+    };
+    vec![
+        separator(state, width),
+        Line::from(Span::styled(footer, footer_style)),
+    ]
+}
+
+/// Visible list rows, with the expanded panel inline after the selected
+/// row if `inline`
+fn repo_lines<'a>(state: &AppState, list_height: usize, area: Rect, inline: bool) -> Vec<Line<'a>> {
+    let theme = &state.theme;
+    let sym = theme.symbols;
+    let max_name_len = state.repos.iter().map(|r| r.name.len()).max().unwrap_or(10);
+
+    // Determine scroll window
+    let (view_start, expanded_rows) = calculate_scroll(state, list_height, inline);
+
+    let mut lines: Vec<Line> = Vec::new();
     let visible_end = state
-        .total()
+        .rows
+        .len()
         .min(view_start + list_height.saturating_sub(expanded_rows));
-    for i in view_start..visible_end {
-        let is_selected = i == state.selected;
+    for (row_index, row) in state
+        .rows
+        .iter()
+        .enumerate()
+        .take(visible_end)
+        .skip(view_start)
+    {
+        let is_selected = row_index == state.selected;
+        let i = match row {
+            Row::Repo(i) => *i,
+            Row::Header { name, collapsed } => {
+                lines.push(header_line(state, name, *collapsed, is_selected));
+                continue;
+            }
+        };
         let name = &state.repos[i].name;
         let status = &state.statuses[i];
 
-        let (icon, icon_style, summ, summ_style) =
-            format_status(status, state.tick, &state.command_name);
+        let (icon, icon_style, mut summ, mut summ_style) =
+            format_status(status, state.tick, &state.command_name, theme);
+        // In watch mode the spinner goes next to the last result
+        if let Some(last) = state.last_result(i) {
+            (_, _, summ, summ_style) = format_status(last, state.tick, &state.command_name, theme);
+        }
 
-        let selector = if is_selected { "▸" } else { " " };
+        let selector = if is_selected { sym.selector } else { " " };
         let selector_style = if is_selected {
-            Style::default().fg(Color::Cyan).bold()
+            theme.accent.bold()
         } else {
             Style::default()
         };
 
-        let name_style = if is_selected {
+        let mut name_style = if is_selected {
             Style::default().bold()
         } else {
             Style::default()
         };
+        if state.matches_search(i) {
+            name_style = name_style.patch(theme.search);
+        }
 
         let padding = max_name_len.saturating_sub(name.len()) + 2;
+        let indent = if state.group_by == GroupMode::None {
+            ""
+        } else {
+            "  "
+        };
 
-        lines.push(Line::from(vec![
-            Span::styled(format!("  {} ", selector), selector_style),
+        let mark = if state.marked.contains(&i) {
+            sym.mark
+        } else {
+            " "
+        };
+        let mut spans = vec![
+            Span::styled(format!(" {}", mark), theme.marked),
+            Span::styled(format!("{} {}", selector, indent), selector_style),
             Span::styled(icon, icon_style),
             Span::raw(" "),
             Span::styled(name.clone(), name_style),
             Span::raw(" ".repeat(padding)),
-            Span::styled(summ, summ_style),
-        ]));
+        ];
+        if state.show_durations {
+            let time = state.elapsed(i).map(format_duration).unwrap_or_default();
+            spans.push(Span::styled(format!("{:>7}  ", time), theme.dim));
+        }
+        let summ_style = if state.changed(i) {
+            summ_style.patch(theme.changed)
+        } else {
+            summ_style
+        };
+        spans.push(Span::styled(summ, summ_style));
+        lines.push(Line::from(spans));
 
         // Expanded content right after the selected row
-        if state.expanded == Some(i) {
+        if inline && state.expanded == Some(i) {
             if let Some(content) = state.expanded_content() {
                 let content_lines: Vec<&str> = content.lines().collect();
-                let max_visible = list_height.saturating_sub(3).max(3);
+                let max_visible = panel_height(state, area.width, area.height);
                 let start = state
                     .scroll_offset
                     .min(content_lines.len().saturating_sub(1));
@@ -82,106 +337,351 @@ pub fn draw(frame: &mut Frame, state: &AppState) {
                 let box_width = area.width.saturating_sub(6) as usize;
 
                 // Top border
+                let border = &sym.border;
                 lines.push(Line::from(Span::styled(
-                    format!("    ┌{}┐", "─".repeat(box_width)),
-                    Style::default().fg(Color::DarkGray),
+                    format!(
+                        "    {}{}{}",
+                        border.top_left,
+                        border.horizontal_top.repeat(box_width),
+                        border.top_right
+                    ),
+                    theme.dim,
                 )));
 
+                let diff = state.command_name == "diff" || ansi::is_diff(&content);
                 for cl in &content_lines[start..end] {
-                    let truncated: String = cl.chars().take(box_width.saturating_sub(2)).collect();
-                    lines.push(Line::from(vec![
-                        Span::styled("    │ ", Style::default().fg(Color::DarkGray)),
-                        Span::raw(truncated),
-                    ]));
+                    let mut spans = vec![Span::styled(
+                        format!("    {} ", border.vertical_left),
+                        theme.dim,
+                    )];
+                    spans.extend(output_line(
+                        cl,
+                        diff,
+                        state.h_scroll,
+                        box_width.saturating_sub(2),
+                        &state.panel_search,
+                        theme,
+                    ));
+                    lines.push(Line::from(spans));
                 }
 
-                // Bottom border (with scroll indicator if needed)
-                if content_lines.len() > max_visible {
-                    let indicator = format!(" [{}-{}/{}] ", start + 1, end, content_lines.len());
-                    let dash_len = box_width.saturating_sub(indicator.len());
-                    lines.push(Line::from(Span::styled(
-                        format!("    └{}{}┘", "─".repeat(dash_len), indicator),
-                        Style::default().fg(Color::DarkGray),
-                    )));
-                } else {
-                    lines.push(Line::from(Span::styled(
-                        format!("    └{}┘", "─".repeat(box_width)),
-                        Style::default().fg(Color::DarkGray),
-                    )));
-                }
+                // Bottom border with scroll position and search matches
+                let indicator = panel_indicator(state, start, end, content_lines.len());
+                let dash_len = box_width.saturating_sub(indicator.chars().count());
+                lines.push(Line::from(Span::styled(
+                    format!(
+                        "    {}{}{}{}",
+                        border.bottom_left,
+                        border.horizontal_bottom.repeat(dash_len),
+                        indicator,
+                        border.bottom_right
+                    ),
+                    theme.dim,
+                )));
             }
         }
     }
+    lines
+}
+
+/// Full-height pane with the output of the focused or selected repo
+fn draw_pane(frame: &mut Frame, state: &AppState, area: Rect) {
+    let theme = &state.theme;
+    let focused = state.expanded.is_some();
+    let border_style = if focused { theme.accent } else { theme.dim };
+    let mut block = Block::bordered()
+        .border_set(theme.symbols.border)
+        .border_style(border_style);
 
-    // Separator
-    lines.push(Line::from(Span::styled(
-        "─".repeat(area.width as usize),
-        Style::default().fg(Color::DarkGray),
-    )));
+    let Some(i) = state.pane_repo() else {
+        frame.render_widget(block, area);
+        return;
+    };
 
-    // Footer
-    let footer = if state.expanded.is_some() {
-        "  [↑↓] scroll  [esc] collapse  [q] quit"
+    let mut title = format!(" {} ", state.repos[i].name);
+    let info = match &state.statuses[i] {
+        RepoStatus::Done { exit_code, .. } => {
+            let mut info = format!("exit {}", exit_code);
+            if let Some(d) = state.durations[i] {
+                info.push_str(&format!(" · {}", format_duration(d)));
+            }
+            info
+        }
+        RepoStatus::Running => match state.elapsed(i) {
+            Some(d) => format!("running · {}", format_duration(d)),
+            None => "running".into(),
+        },
+        RepoStatus::Skipped { .. } => "skipped".into(),
+        RepoStatus::Pending | RepoStatus::Blocked { .. } => "pending".into(),
+    };
+    let info_style = match &state.statuses[i] {
+        RepoStatus::Done { exit_code, .. } if *exit_code != 0 => theme.failed,
+        _ => theme.dim,
+    };
+    title.push_str(theme.symbols.border.horizontal_top);
+    title.push(' ');
+
+    let content = state.output(i);
+    let content_lines: Vec<&str> = content.lines().collect();
+    let height = area.height.saturating_sub(2) as usize;
+    let width = area.width.saturating_sub(3) as usize;
+    // Scrolling only applies once the pane has focus
+    let (offset, h_scroll, query) = if focused {
+        (
+            state.scroll_offset,
+            state.h_scroll,
+            state.panel_search.as_str(),
+        )
+    } else {
+        (0, 0, "")
+    };
+    let start = offset.min(content_lines.len().saturating_sub(1));
+    let end = (start + height).min(content_lines.len());
+
+    let diff = state.command_name == "diff" || ansi::is_diff(&content);
+    let lines: Vec<Line> = content_lines[start..end]
+        .iter()
+        .map(|cl| {
+            let mut spans = vec![Span::raw(" ")];
+            spans.extend(output_line(cl, diff, h_scroll, width, query, theme));
+            Line::from(spans)
+        })
+        .collect();
+
+    block = block.title(Line::from(vec![
+        Span::styled(title, Style::default().bold()),
+        Span::styled(format!("{} ", info), info_style),
+    ]));
+    let indicator = if focused {
+        panel_indicator(state, start, end, content_lines.len())
+    } else if content_lines.len() > height {
+        format!(" [{}-{}/{}] ", start + 1, end, content_lines.len())
+    } else {
+        String::new()
+    };
+    if !indicator.is_empty() {
+        block = block.title_bottom(Line::from(indicator).right_aligned());
+    }
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Search matches, pan column and line range shown on the panel's bottom
+/// border
+fn panel_indicator(state: &AppState, start: usize, end: usize, total: usize) -> String {
+    let mut indicators = Vec::new();
+    if !state.panel_search.is_empty() {
+        let matches = state.panel_matches();
+        match matches.iter().position(|&m| m >= start) {
+            _ if matches.is_empty() => indicators.push(" no matches ".into()),
+            Some(n) => indicators.push(format!(" match {}/{} ", n + 1, matches.len())),
+            None => indicators.push(format!(
+                " {} match{} ",
+                matches.len(),
+                if matches.len() == 1 { "" } else { "es" }
+            )),
+        }
+    }
+    if state.h_scroll > 0 {
+        indicators.push(format!(" col {}+ ", state.h_scroll + 1));
+    }
+    if start > 0 || end < total {
+        indicators.push(format!(" [{}-{}/{}] ", start + 1, end, total));
+    }
+    indicators.concat()
+}
+
+fn header_line<'a>(state: &AppState, name: &str, collapsed: bool, is_selected: bool) -> Line<'a> {
+    let (total, done, failed) = state.group_counts(name);
+    let mut counts = format!("{}/{} done", done, total);
+    if failed > 0 {
+        counts.push_str(&format!(", {} failed", failed));
+    }
+    let theme = &state.theme;
+    let selector = if is_selected {
+        theme.symbols.selector
     } else {
-        "  [↑↓/jk] navigate  [enter] expand  [q] quit"
+        " "
     };
-    lines.push(Line::from(Span::styled(
-        footer,
-        Style::default().fg(Color::DarkGray),
-    )));
+    Line::from(vec![
+        Span::styled(format!("  {} ", selector), theme.accent.bold()),
+        Span::styled(
+            format!("{} {}", if collapsed { "+" } else { "-" }, name),
+            Style::default().bold(),
+        ),
+        Span::raw("  "),
+        Span::styled(counts, if failed > 0 { theme.failed } else { theme.dim }),
+    ])
+}
 
-    let paragraph = Paragraph::new(lines);
-    frame.render_widget(paragraph, area);
+/// Compact duration: `0.4s`, `12.3s`, `2m05s`
+pub fn format_duration(d: Duration) -> String {
+    // Decide on tenths so 59.96s doesn't show as "60.0s"
+    let tenths = (d.as_secs_f64() * 10.0).round() as u64;
+    if tenths < 600 {
+        format!("{}.{}s", tenths / 10, tenths % 10)
+    } else {
+        let secs = d.as_secs().max(60);
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    }
+}
+
+/// Watch interval in the largest whole unit: `30s`, `5m`, `1h`
+fn format_interval(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs.is_multiple_of(3600) {
+        format!("{}h", secs / 3600)
+    } else if secs.is_multiple_of(60) {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{}s", secs)
+    }
 }
 
 fn format_status(
     status: &RepoStatus,
     tick: usize,
     command_name: &str,
+    theme: &Theme,
 ) -> (String, Style, String, Style) {
+    let sym = theme.symbols;
     match status {
-        RepoStatus::Pending => (
-            " ".into(),
-            Style::default().fg(Color::DarkGray),
-            "waiting...".into(),
-            Style::default().fg(Color::DarkGray),
+        RepoStatus::Pending => (" ".into(), theme.dim, "waiting...".into(), theme.dim),
+        RepoStatus::Blocked { on } => (
+            sym.blocked.into(),
+            theme.blocked,
+            format!("waiting for {}", on.join(", ")),
+            theme.dim,
         ),
         RepoStatus::Running => (
-            spinner::frame(tick).to_string(),
-            Style::default().fg(Color::Yellow),
+            spinner::frame(sym.spinner, tick).to_string(),
+            theme.running,
             running_text(command_name),
-            Style::default().fg(Color::Yellow),
+            theme.running,
         ),
         RepoStatus::Done {
             summary, exit_code, ..
         } => {
             if *exit_code == 0 {
-                (
-                    "✓".into(),
-                    Style::default().fg(Color::Green),
-                    summary.clone(),
-                    Style::default().fg(Color::DarkGray),
-                )
+                (sym.ok.into(), theme.ok, ansi::strip(summary), theme.dim)
             } else {
                 (
-                    "✗".into(),
-                    Style::default().fg(Color::Red),
-                    summary.clone(),
-                    Style::default().fg(Color::Red),
+                    sym.failed.into(),
+                    theme.failed,
+                    ansi::strip(summary),
+                    theme.failed,
                 )
             }
         }
-        RepoStatus::Skipped { reason } => (
-            "-".into(),
-            Style::default().fg(Color::DarkGray),
-            reason.clone(),
-            Style::default().fg(Color::DarkGray),
-        ),
+        RepoStatus::Skipped { reason } => {
+            (sym.skipped.into(), theme.dim, reason.clone(), theme.dim)
+        }
+    }
+}
+
+/// Output lines visible in the expanded panel for a terminal this size
+pub fn panel_height(state: &AppState, term_width: u16, term_height: u16) -> usize {
+    let list_height = term_height.saturating_sub(4) as usize;
+    if is_split(state, term_width) {
+        // Full height minus the pane's borders
+        list_height.saturating_sub(2).max(1)
+    } else {
+        list_height.saturating_sub(3).max(3)
+    }
+}
+
+/// Spans for one line of expanded output: ANSI colors (or diff colors),
+/// scrolled `h_scroll` columns, cut to `width`, with matches of `query`
+/// highlighted
+fn output_line<'a>(
+    line: &str,
+    diff: bool,
+    h_scroll: usize,
+    width: usize,
+    query: &str,
+    theme: &Theme,
+) -> Vec<Span<'a>> {
+    let segments = if line.contains('\x1b') {
+        ansi::parse_line(line)
+            .into_iter()
+            .map(|(text, style)| (text, theme.output(style)))
+            .collect()
+    } else if diff {
+        vec![(line.to_string(), ansi::diff_style(line, theme))]
+    } else {
+        vec![(line.to_string(), Style::default())]
+    };
+
+    // Horizontal window
+    let mut skip = h_scroll;
+    let mut room = width;
+    let mut visible: Vec<(String, Style)> = Vec::new();
+    for (text, style) in segments {
+        let n = text.chars().count();
+        if skip >= n {
+            skip -= n;
+            continue;
+        }
+        let part: String = text.chars().skip(skip).take(room).collect();
+        skip = 0;
+        room -= part.chars().count();
+        visible.push((part, style));
+        if room == 0 {
+            break;
+        }
+    }
+
+    if query.is_empty() {
+        return visible
+            .into_iter()
+            .map(|(text, style)| Span::styled(text, style))
+            .collect();
+    }
+
+    // Byte ranges of matches in the visible text. ASCII lowercasing keeps
+    // byte offsets valid.
+    let plain: String = visible.iter().map(|(t, _)| t.as_str()).collect();
+    let lower = plain.to_ascii_lowercase();
+    let query = query.to_ascii_lowercase();
+    let mut matches = Vec::new();
+    let mut pos = 0;
+    while let Some(found) = lower[pos..].find(&query) {
+        matches.push((pos + found, pos + found + query.len()));
+        pos += found + query.len();
     }
+
+    let match_style = theme.highlight;
+    let mut spans = Vec::new();
+    let mut offset = 0;
+    for (text, style) in visible {
+        let seg_end = offset + text.len();
+        let mut cut = offset;
+        for &(m_start, m_end) in &matches {
+            let (a, b) = (m_start.max(cut), m_end.min(seg_end));
+            if a >= b {
+                continue;
+            }
+            if a > cut {
+                spans.push(Span::styled(
+                    text[cut - offset..a - offset].to_string(),
+                    style,
+                ));
+            }
+            spans.push(Span::styled(
+                text[a - offset..b - offset].to_string(),
+                match_style,
+            ));
+            cut = b;
+        }
+        if cut < seg_end {
+            spans.push(Span::styled(text[cut - offset..].to_string(), style));
+        }
+        offset = seg_end;
+    }
+    spans
 }
 
-fn calculate_scroll(state: &AppState, list_height: usize) -> (usize, usize) {
-    let expanded_rows = if state.expanded.is_some() {
+fn calculate_scroll(state: &AppState, list_height: usize, inline: bool) -> (usize, usize) {
+    let expanded_rows = if inline && state.expanded.is_some() {
         if let Some(content) = state.expanded_content() {
             let content_lines = content.lines().count();
             let max_visible = list_height.saturating_sub(3).max(3);
@@ -212,7 +712,29 @@ fn running_text(command: &str) -> String {
         "push" => "pushing...".into(),
         "fetch" => "fetching...".into(),
         "checkout" => "cloning...".into(),
+        "unshallow" => "deepening...".into(),
+        "mirror" => "mirroring...".into(),
+        "worktree add" => "adding worktree...".into(),
         "run" => "running...".into(),
         _ => "running...".into(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        let ms = Duration::from_millis;
+        assert_eq!(format_duration(ms(0)), "0.0s");
+        assert_eq!(format_duration(ms(1234)), "1.2s");
+        assert_eq!(format_duration(ms(59_940)), "59.9s");
+        assert_eq!(format_duration(ms(59_960)), "1m00s");
+        assert_eq!(format_duration(ms(61_900)), "1m01s");
+        assert_eq!(
+            format_duration(Duration::from_secs(2 * 3600 + 5)),
+            "120m05s"
+        );
+    }
+}
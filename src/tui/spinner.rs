@@ -1,5 +1,8 @@
-const FRAMES: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+pub const FRAMES: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
 
-pub fn frame(tick: usize) -> char {
-    FRAMES[tick % FRAMES.len()]
+/// Frames for terminals without braille glyphs
+pub const ASCII_FRAMES: &[char] = &['|', '/', '-', '\\'];
+
+pub fn frame(frames: &[char], tick: usize) -> char {
+    frames[tick % frames.len()]
 }
@@ -1,11 +1,22 @@
+use super::ansi;
+use super::keys::Keymap;
+use super::theme::Theme;
 use crate::config::Repo;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum RepoStatus {
     Pending,
+    /// Waiting for dependencies to finish
+    Blocked {
+        on: Vec<String>,
+    },
     Running,
     Done {
         summary: String,
+        /// False when the summary means "nothing to report"
+        changed: bool,
         stdout: String,
         stderr: String,
         exit_code: i32,
@@ -15,6 +26,26 @@ pub enum RepoStatus {
     },
 }
 
+/// Case-insensitive subsequence match, so `apgw` finds `api-gateway`
+pub fn fuzzy_match(query: &str, text: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|q| text.any(|t| t == q))
+}
+
+/// Sort key for `SortMode::Status`: failures first
+fn status_rank(status: &RepoStatus) -> u8 {
+    match status {
+        RepoStatus::Done { exit_code, .. } if *exit_code != 0 => 0,
+        RepoStatus::Running => 1,
+        RepoStatus::Blocked { .. } | RepoStatus::Pending => 2,
+        RepoStatus::Done { .. } => 3,
+        RepoStatus::Skipped { .. } => 4,
+    }
+}
+
 impl RepoStatus {
     pub fn is_done(&self) -> bool {
         matches!(self, RepoStatus::Done { .. } | RepoStatus::Skipped { .. })
@@ -23,17 +54,200 @@ impl RepoStatus {
     pub fn is_failed(&self) -> bool {
         matches!(self, RepoStatus::Done { exit_code, .. } if *exit_code != 0)
     }
+
+    /// Exit code and summary of a finished repo, for spotting changes
+    /// between watch cycles
+    pub fn result(&self) -> Option<String> {
+        match self {
+            RepoStatus::Done {
+                summary, exit_code, ..
+            } => Some(format!("{} {}", exit_code, summary)),
+            RepoStatus::Skipped { reason } => Some(reason.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Order of repo rows in the list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortMode {
+    /// Config order (by path)
+    #[default]
+    Config,
+    /// Failures first, then running, waiting, succeeded and skipped
+    Status,
+    Name,
+    Path,
+    /// Slowest first
+    Duration,
+}
+
+impl SortMode {
+    pub fn next(self) -> SortMode {
+        match self {
+            SortMode::Config => SortMode::Status,
+            SortMode::Status => SortMode::Name,
+            SortMode::Name => SortMode::Path,
+            SortMode::Path => SortMode::Duration,
+            SortMode::Duration => SortMode::Config,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortMode::Config => "config",
+            SortMode::Status => "status",
+            SortMode::Name => "name",
+            SortMode::Path => "path",
+            SortMode::Duration => "duration",
+        }
+    }
+}
+
+/// How repo rows are grouped under headers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GroupMode {
+    #[default]
+    None,
+    /// By the directory containing the repo
+    Directory,
+    /// By the section's `group =` key
+    Group,
+}
+
+impl GroupMode {
+    pub fn next(self) -> GroupMode {
+        match self {
+            GroupMode::None => GroupMode::Directory,
+            GroupMode::Directory => GroupMode::Group,
+            GroupMode::Group => GroupMode::None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            GroupMode::None => "none",
+            GroupMode::Directory => "directory",
+            GroupMode::Group => "group",
+        }
+    }
+}
+
+/// Quick filter restricting rows to one kind of result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Only {
+    Failed,
+    /// Succeeded with something to report (not clean or up to date)
+    Dirty,
+    Running,
+}
+
+impl Only {
+    pub fn label(self) -> &'static str {
+        match self {
+            Only::Failed => "failed",
+            Only::Dirty => "dirty",
+            Only::Running => "running",
+        }
+    }
+}
+
+/// Follow-up operation run from the action menu on the marked repos
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Pull,
+    Push,
+    Status,
+    /// Shell command typed after choosing "run"
+    Run(String),
+}
+
+/// Action menu entries: shortcut key and label
+pub const MENU: &[(char, &str)] = &[
+    ('u', "pull"),
+    ('p', "push"),
+    ('s', "status"),
+    ('r', "run command..."),
+];
+
+/// A line in the repo list
+#[derive(Debug, Clone, PartialEq)]
+pub enum Row {
+    Header {
+        name: String,
+        collapsed: bool,
+    },
+    /// Index into `repos` and `statuses`
+    Repo(usize),
 }
 
 pub struct AppState {
     pub repos: Vec<Repo>,
     pub statuses: Vec<RepoStatus>,
+    /// Visible rows, rebuilt by `refresh_rows` as sorting and statuses change
+    pub rows: Vec<Row>,
+    /// Index into `rows`
     pub selected: usize,
     pub expanded: Option<usize>,
     pub scroll_offset: usize,
+    /// Columns scrolled right in the expanded panel
+    pub h_scroll: usize,
+    /// Search query within the expanded panel
+    pub panel_search: String,
+    pub panel_searching: bool,
     pub tick: usize,
     pub command_name: String,
+    /// Full command line the results come from, for run history
+    pub command_args: Vec<String>,
+    /// Shown after the command name, e.g. when replaying a recorded run
+    pub subtitle: Option<String>,
     pub all_done: bool,
+    pub started_at: Vec<Option<Instant>>,
+    /// How long each finished repo's operation took
+    pub durations: Vec<Option<Duration>>,
+    /// Show each repo's duration in its row
+    pub show_durations: bool,
+    pub created_at: Instant,
+    /// When the last repo finished
+    pub finished_at: Option<Instant>,
+    pub sort: SortMode,
+    pub group_by: GroupMode,
+    /// Names of collapsed group headers
+    pub collapsed: HashSet<String>,
+    pub only: Option<Only>,
+    pub hide_skipped: bool,
+    /// Search query typed after `/`
+    pub search: String,
+    /// Whether keys go to the search query
+    pub searching: bool,
+    /// Show output in a pane beside the list instead of inline
+    pub split: bool,
+    /// Shown in the footer until the next key press
+    pub message: Option<String>,
+    /// Repos to run again, taken by the event source
+    pub rerun: Vec<usize>,
+    /// Repos marked for follow-up actions
+    pub marked: BTreeSet<usize>,
+    /// Highlighted entry of the open action menu
+    pub menu: Option<usize>,
+    /// Command being typed for the menu's "run" entry
+    pub run_input: Option<String>,
+    /// Actions chosen from the menu, taken by the event source
+    pub actions: Vec<(Action, Vec<usize>)>,
+    /// Line being typed at the `:` prompt
+    pub prompt: Option<String>,
+    /// Lines entered at the `:` prompt, taken by the event source
+    pub commands: Vec<String>,
+    /// Quit as soon as every repo has finished
+    pub exit_when_done: bool,
+    /// Interval between runs in watch mode
+    pub watch: Option<Duration>,
+    /// When the next watch cycle starts, once the current one is over
+    pub next_cycle: Option<Instant>,
+    /// Each repo's status when the current watch cycle started
+    pub previous: Vec<RepoStatus>,
+    pub theme: Theme,
+    pub keymap: Keymap,
 }
 
 impl AppState {
@@ -42,12 +256,45 @@ impl AppState {
         Self {
             repos,
             statuses: vec![RepoStatus::Pending; n],
+            rows: (0..n).map(Row::Repo).collect(),
             selected: 0,
             expanded: None,
             scroll_offset: 0,
+            h_scroll: 0,
+            panel_search: String::new(),
+            panel_searching: false,
             tick: 0,
             command_name: command_name.to_string(),
+            command_args: Vec::new(),
+            subtitle: None,
             all_done: false,
+            started_at: vec![None; n],
+            durations: vec![None; n],
+            show_durations: false,
+            created_at: Instant::now(),
+            finished_at: None,
+            sort: SortMode::default(),
+            group_by: GroupMode::default(),
+            collapsed: HashSet::new(),
+            only: None,
+            hide_skipped: false,
+            search: String::new(),
+            searching: false,
+            split: false,
+            message: None,
+            rerun: Vec::new(),
+            marked: BTreeSet::new(),
+            menu: None,
+            run_input: None,
+            actions: Vec::new(),
+            prompt: None,
+            commands: Vec::new(),
+            exit_when_done: false,
+            watch: None,
+            next_cycle: None,
+            previous: Vec::new(),
+            theme: Theme::default(),
+            keymap: Keymap::default(),
         }
     }
 
@@ -63,6 +310,262 @@ impl AppState {
         self.repos.len()
     }
 
+    /// Time from start until every repo finished, if they all have
+    pub fn wall_time(&self) -> Option<Duration> {
+        self.finished_at.map(|t| t - self.created_at)
+    }
+
+    /// Finished duration, or time so far for a running repo
+    pub fn elapsed(&self, index: usize) -> Option<Duration> {
+        match self.statuses[index] {
+            RepoStatus::Running => self.started_at[index].map(|t| t.elapsed()),
+            _ => self.durations[index],
+        }
+    }
+
+    /// Repo on the selected row, `None` on a group header
+    pub fn selected_repo(&self) -> Option<usize> {
+        match self.rows.get(self.selected) {
+            Some(Row::Repo(i)) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Rebuild `rows` from the sort and group modes, keeping the selection
+    /// on the same repo or header
+    pub fn refresh_rows(&mut self) {
+        let current = self.rows.get(self.selected).cloned();
+
+        let mut order: Vec<usize> = (0..self.total()).filter(|&i| self.is_shown(i)).collect();
+        match self.sort {
+            SortMode::Config => {}
+            SortMode::Status => order.sort_by_key(|&i| status_rank(&self.statuses[i])),
+            SortMode::Name => order.sort_by(|&a, &b| self.repos[a].name.cmp(&self.repos[b].name)),
+            SortMode::Path => order.sort_by(|&a, &b| self.repos[a].path.cmp(&self.repos[b].path)),
+            SortMode::Duration => {
+                order.sort_by_key(|&i| std::cmp::Reverse(self.elapsed(i)));
+            }
+        }
+
+        self.rows = match self.group_by {
+            GroupMode::None => order.into_iter().map(Row::Repo).collect(),
+            mode => {
+                let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+                for i in order {
+                    groups.entry(self.group_of(i, mode)).or_default().push(i);
+                }
+                let mut rows = Vec::new();
+                for (name, members) in groups {
+                    let collapsed = self.collapsed.contains(&name);
+                    rows.push(Row::Header {
+                        name: name.clone(),
+                        collapsed,
+                    });
+                    if !collapsed {
+                        rows.extend(members.into_iter().map(Row::Repo));
+                    }
+                }
+                rows
+            }
+        };
+
+        let same_row = |row: &Row| match (row, &current) {
+            (Row::Header { name, .. }, Some(Row::Header { name: cur, .. })) => name == cur,
+            (row, Some(cur)) => row == cur,
+            (_, None) => false,
+        };
+        self.selected = match self.rows.iter().position(same_row) {
+            Some(pos) => pos,
+            None => self.selected.min(self.rows.len().saturating_sub(1)),
+        };
+        if self
+            .expanded
+            .is_some_and(|e| !self.rows.contains(&Row::Repo(e)))
+        {
+            self.collapse();
+        }
+    }
+
+    /// Whether a repo passes the active filters
+    pub fn is_shown(&self, index: usize) -> bool {
+        let status = &self.statuses[index];
+        if self.hide_skipped && matches!(status, RepoStatus::Skipped { .. }) {
+            return false;
+        }
+        match self.only {
+            None => true,
+            Some(only) => self.matches_only(index, only),
+        }
+    }
+
+    /// Whether a repo's result is of the given kind
+    pub fn matches_only(&self, index: usize, only: Only) -> bool {
+        let status = &self.statuses[index];
+        match only {
+            Only::Failed => status.is_failed(),
+            Only::Running => *status == RepoStatus::Running,
+            Only::Dirty => match status {
+                RepoStatus::Done {
+                    changed, exit_code, ..
+                } => *exit_code == 0 && *changed,
+                _ => false,
+            },
+        }
+    }
+
+    /// Whether a repo's result differs from the previous watch cycle's
+    pub fn changed(&self, index: usize) -> bool {
+        match self.previous.get(index).and_then(RepoStatus::result) {
+            Some(before) => self.statuses[index]
+                .result()
+                .is_some_and(|now| now != before),
+            None => false,
+        }
+    }
+
+    /// The previous watch cycle's result of a repo that's running again,
+    /// shown until the new one arrives
+    pub fn last_result(&self, index: usize) -> Option<&RepoStatus> {
+        if self.statuses[index] != RepoStatus::Running {
+            return None;
+        }
+        self.previous.get(index).filter(|s| s.is_done())
+    }
+
+    /// Mark or unmark the selected repo and move to the next row
+    pub fn toggle_mark(&mut self) {
+        if let Some(i) = self.selected_repo() {
+            if !self.marked.remove(&i) {
+                self.marked.insert(i);
+            }
+            self.move_down();
+        }
+    }
+
+    /// Mark every repo of the given kind, in addition to those marked
+    pub fn mark_all(&mut self, only: Only) {
+        let matching: Vec<usize> = (0..self.total())
+            .filter(|&i| self.matches_only(i, only))
+            .collect();
+        self.marked.extend(matching);
+    }
+
+    /// Repos an action applies to: the marked ones, else the selected one
+    pub fn action_targets(&self) -> Vec<usize> {
+        if self.marked.is_empty() {
+            self.selected_repo().into_iter().collect()
+        } else {
+            self.marked.iter().copied().collect()
+        }
+    }
+
+    /// Queue a repo to run again after a tool was opened in it, unless it's
+    /// still running
+    pub fn queue_rerun(&mut self, index: usize) {
+        if self.statuses[index].is_done() {
+            self.rerun.push(index);
+        }
+    }
+
+    /// Queue `action` for the targets that aren't still running
+    pub fn dispatch(&mut self, action: Action) {
+        let targets: Vec<usize> = self
+            .action_targets()
+            .into_iter()
+            .filter(|&i| self.statuses[i].is_done())
+            .collect();
+        if targets.is_empty() {
+            self.message = Some("nothing to run: no finished repo selected".into());
+            return;
+        }
+        self.actions.push((action, targets));
+    }
+
+    /// Repos hidden by the filters
+    pub fn hidden_count(&self) -> usize {
+        (0..self.total()).filter(|&i| !self.is_shown(i)).count()
+    }
+
+    /// Toggle an "only" filter; choosing the active one turns it off
+    pub fn toggle_only(&mut self, only: Only) {
+        self.only = if self.only == Some(only) {
+            None
+        } else {
+            Some(only)
+        };
+        self.refresh_rows();
+    }
+
+    pub fn toggle_hide_skipped(&mut self) {
+        self.hide_skipped = !self.hide_skipped;
+        self.refresh_rows();
+    }
+
+    pub fn clear_filters(&mut self) {
+        self.only = None;
+        self.hide_skipped = false;
+        self.search.clear();
+        self.refresh_rows();
+    }
+
+    /// Whether a repo's name matches the search query
+    pub fn matches_search(&self, index: usize) -> bool {
+        !self.search.is_empty() && fuzzy_match(&self.search, &self.repos[index].name)
+    }
+
+    /// Select the next (or previous) row matching the search, starting
+    /// from the selected row itself if `include_current`
+    pub fn search_jump(&mut self, forward: bool, include_current: bool) {
+        let n = self.rows.len();
+        if n == 0 || self.search.is_empty() {
+            return;
+        }
+        let first = if include_current { 0 } else { 1 };
+        for step in first..=n {
+            let pos = if forward {
+                (self.selected + step) % n
+            } else {
+                (self.selected + n * 2 - step) % n
+            };
+            if matches!(self.rows[pos], Row::Repo(i) if self.matches_search(i)) {
+                self.selected = pos;
+                return;
+            }
+        }
+    }
+
+    fn group_of(&self, index: usize, mode: GroupMode) -> String {
+        let repo = &self.repos[index];
+        match mode {
+            GroupMode::Directory => match repo.section.rsplit_once('/') {
+                Some((dir, _)) => dir.to_string(),
+                None => ".".into(),
+            },
+            GroupMode::Group => repo.group.clone().unwrap_or_else(|| "(no group)".into()),
+            GroupMode::None => String::new(),
+        }
+    }
+
+    /// Repos under a group header: (total, finished, failed)
+    pub fn group_counts(&self, name: &str) -> (usize, usize, usize) {
+        let members: Vec<usize> = (0..self.total())
+            .filter(|&i| self.is_shown(i) && self.group_of(i, self.group_by) == name)
+            .collect();
+        let done = members.iter().filter(|&&i| self.statuses[i].is_done());
+        let failed = members.iter().filter(|&&i| self.statuses[i].is_failed());
+        (members.len(), done.count(), failed.count())
+    }
+
+    pub fn cycle_sort(&mut self) {
+        self.sort = self.sort.next();
+        self.refresh_rows();
+    }
+
+    pub fn cycle_group(&mut self) {
+        self.group_by = self.group_by.next();
+        self.refresh_rows();
+    }
+
     pub fn move_up(&mut self) {
         if self.selected > 0 {
             self.selected -= 1;
@@ -70,24 +573,119 @@ impl AppState {
     }
 
     pub fn move_down(&mut self) {
-        if self.selected + 1 < self.total() {
+        if self.selected + 1 < self.rows.len() {
             self.selected += 1;
         }
     }
 
+    /// Expand the selected repo's output, or fold/unfold a group header
     pub fn toggle_expand(&mut self) {
-        if self.expanded == Some(self.selected) {
-            self.expanded = None;
-            self.scroll_offset = 0;
+        if let Some(Row::Header { name, collapsed }) = self.rows.get(self.selected) {
+            let name = name.clone();
+            if *collapsed {
+                self.collapsed.remove(&name);
+            } else {
+                self.collapsed.insert(name);
+            }
+            self.refresh_rows();
+            return;
+        }
+        if self.expanded.is_some() && self.expanded == self.selected_repo() {
+            self.collapse();
         } else {
-            self.expanded = Some(self.selected);
-            self.scroll_offset = 0;
+            self.expanded = self.selected_repo();
+            self.reset_panel();
         }
     }
 
     pub fn collapse(&mut self) {
         self.expanded = None;
+        self.reset_panel();
+    }
+
+    fn reset_panel(&mut self) {
         self.scroll_offset = 0;
+        self.h_scroll = 0;
+        self.panel_search.clear();
+        self.panel_searching = false;
+    }
+
+    /// Scroll the panel by `lines`, up if negative, keeping the last line
+    /// reachable
+    pub fn scroll_by(&mut self, lines: isize, max_lines: usize) {
+        let target = self.scroll_offset.saturating_add_signed(lines);
+        self.scroll_offset = target.min(max_lines.saturating_sub(1));
+    }
+
+    /// Show the last `page` lines of the panel
+    pub fn scroll_to_bottom(&mut self, page: usize, max_lines: usize) {
+        self.scroll_offset = max_lines.saturating_sub(page);
+    }
+
+    pub fn scroll_left(&mut self, cols: usize) {
+        self.h_scroll = self.h_scroll.saturating_sub(cols);
+    }
+
+    /// Pan right by `cols`, as long as the longest of the `page` visible
+    /// lines still has text left of the new position
+    pub fn scroll_right(&mut self, cols: usize, page: usize) {
+        let longest = self
+            .expanded_content()
+            .map(|c| {
+                c.lines()
+                    .skip(self.scroll_offset)
+                    .take(page)
+                    .map(|l| ansi::strip(l).chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .unwrap_or(0);
+        let target = self.h_scroll + cols;
+        self.h_scroll = if target < longest {
+            target
+        } else {
+            self.h_scroll.min(longest.saturating_sub(1))
+        };
+    }
+
+    /// Lines of the expanded output containing the panel search
+    /// (case-insensitive)
+    pub fn panel_matches(&self) -> Vec<usize> {
+        if self.panel_search.is_empty() {
+            return Vec::new();
+        }
+        let query = self.panel_search.to_ascii_lowercase();
+        self.expanded_content()
+            .map(|c| {
+                c.lines()
+                    .enumerate()
+                    .filter(|(_, l)| ansi::strip(l).to_ascii_lowercase().contains(&query))
+                    .map(|(n, _)| n)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Scroll the next (or previous) matching line to the top of the panel,
+    /// starting at the current top line if `include_current`
+    pub fn panel_search_jump(&mut self, forward: bool, include_current: bool) {
+        let matches = self.panel_matches();
+        let current = self.scroll_offset;
+        let next = if forward {
+            matches
+                .iter()
+                .find(|&&m| m > current || (include_current && m == current))
+                .or(matches.first())
+        } else {
+            matches
+                .iter()
+                .rev()
+                .find(|&&m| m < current)
+                .or(matches.last())
+        };
+        if let Some(&line) = next {
+            self.scroll_offset = line;
+        }
     }
 
     pub fn scroll_up(&mut self) {
@@ -101,7 +699,16 @@ impl AppState {
     }
 
     pub fn expanded_content(&self) -> Option<String> {
-        let idx = self.expanded?;
+        self.expanded.map(|i| self.output(i))
+    }
+
+    /// Repo shown in the split pane: the focused one, else the selection
+    pub fn pane_repo(&self) -> Option<usize> {
+        self.expanded.or_else(|| self.selected_repo())
+    }
+
+    /// Full output of a repo, or a placeholder while it has none
+    pub fn output(&self, idx: usize) -> String {
         match &self.statuses[idx] {
             RepoStatus::Done { stdout, stderr, .. } => {
                 let mut content = String::new();
@@ -117,11 +724,12 @@ impl AppState {
                 if content.is_empty() {
                     content.push_str("(no output)");
                 }
-                Some(content)
+                content
             }
-            RepoStatus::Running => Some("(still running...)".into()),
-            RepoStatus::Pending => Some("(pending...)".into()),
-            RepoStatus::Skipped { reason } => Some(format!("(skipped: {})", reason)),
+            RepoStatus::Running => "(still running...)".into(),
+            RepoStatus::Pending => "(pending...)".into(),
+            RepoStatus::Blocked { on } => format!("(waiting for {})", on.join(", ")),
+            RepoStatus::Skipped { reason } => format!("(skipped: {})", reason),
         }
     }
 
@@ -136,3 +744,202 @@ impl AppState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn state(sections: &[&str]) -> AppState {
+        let repos = sections
+            .iter()
+            .map(|s| Repo {
+                name: s.rsplit('/').next().unwrap().to_string(),
+                section: s.to_string(),
+                path: PathBuf::from("/work").join(s),
+                clone: None,
+                submodules: false,
+                vcs: Default::default(),
+                depends: Vec::new(),
+                group: None,
+            })
+            .collect();
+        AppState::new(repos, "status")
+    }
+
+    fn done(exit_code: i32) -> RepoStatus {
+        RepoStatus::Done {
+            summary: String::new(),
+            changed: true,
+            stdout: String::new(),
+            stderr: String::new(),
+            exit_code,
+        }
+    }
+
+    #[test]
+    fn test_sort_by_status_keeps_selection() {
+        let mut state = state(&["a", "b", "c"]);
+        state.statuses = vec![done(0), RepoStatus::Running, done(1)];
+        state.selected = 1;
+        state.cycle_sort();
+        assert_eq!(state.rows, vec![Row::Repo(2), Row::Repo(1), Row::Repo(0)]);
+        assert_eq!(state.selected_repo(), Some(1));
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert!(fuzzy_match("apgw", "api-gateway"));
+        assert!(fuzzy_match("API", "api-gateway"));
+        assert!(!fuzzy_match("wag", "api-gateway"));
+    }
+
+    #[test]
+    fn test_filter_keeps_indices() {
+        let mut state = state(&["a", "b", "c"]);
+        state.statuses = vec![done(1), done(0), done(1)];
+        state.toggle_only(Only::Failed);
+        assert_eq!(state.rows, vec![Row::Repo(0), Row::Repo(2)]);
+        assert_eq!(state.hidden_count(), 1);
+    }
+
+    #[test]
+    fn test_mark_and_dispatch() {
+        let mut state = state(&["a", "b", "c"]);
+        state.statuses = vec![done(1), RepoStatus::Running, done(0)];
+        state.dispatch(Action::Pull);
+        assert_eq!(state.actions, vec![(Action::Pull, vec![0])]);
+
+        state.mark_all(Only::Failed);
+        state.selected = 1;
+        state.toggle_mark();
+        assert_eq!(state.selected, 2);
+        state.dispatch(Action::Status);
+        // The running repo is marked but not dispatched
+        assert_eq!(state.actions[1], (Action::Status, vec![0]));
+    }
+
+    #[test]
+    fn test_queue_rerun_skips_running_repos() {
+        let mut state = state(&["a", "b"]);
+        state.statuses = vec![done(0), RepoStatus::Running];
+        state.queue_rerun(1);
+        assert!(state.rerun.is_empty());
+        state.queue_rerun(0);
+        assert_eq!(state.rerun, vec![0]);
+    }
+
+    #[test]
+    fn test_only_dirty_skips_quiet_results() {
+        let mut state = state(&["a", "b", "c"]);
+        state.statuses = vec![done(0), done(0), done(1)];
+        if let RepoStatus::Done { changed, .. } = &mut state.statuses[1] {
+            *changed = false;
+        }
+        state.mark_all(Only::Dirty);
+        assert_eq!(state.marked.iter().copied().collect::<Vec<_>>(), vec![0]);
+    }
+
+    #[test]
+    fn test_changed_since_previous_cycle() {
+        let mut state = state(&["a", "b", "c"]);
+        state.statuses = vec![done(0), done(0), RepoStatus::Pending];
+        state.previous = state.statuses.clone();
+        state.statuses[1] = done(1);
+        state.statuses[2] = done(0);
+        assert!(!state.changed(0));
+        assert!(state.changed(1));
+        // No earlier result to compare with
+        assert!(!state.changed(2));
+
+        state.statuses = vec![RepoStatus::Running, done(0), RepoStatus::Running];
+        assert_eq!(state.last_result(0), Some(&done(0)));
+        assert_eq!(state.last_result(1), None);
+        assert_eq!(state.last_result(2), None);
+    }
+
+    #[test]
+    fn test_group_by_directory_and_collapse() {
+        let mut state = state(&["x/a", "y/b", "x/c"]);
+        state.cycle_group();
+        assert_eq!(state.rows.len(), 5);
+        state.selected = 0;
+        state.toggle_expand();
+        assert_eq!(
+            state.rows,
+            vec![
+                Row::Header {
+                    name: "x".into(),
+                    collapsed: true
+                },
+                Row::Header {
+                    name: "y".into(),
+                    collapsed: false
+                },
+                Row::Repo(1),
+            ]
+        );
+    }
+
+    fn expanded_with(stdout: &str) -> AppState {
+        let mut state = state(&["a"]);
+        state.statuses[0] = RepoStatus::Done {
+            summary: String::new(),
+            changed: true,
+            stdout: stdout.into(),
+            stderr: String::new(),
+            exit_code: 0,
+        };
+        state.toggle_expand();
+        state
+    }
+
+    #[test]
+    fn test_scroll_by_stays_in_output() {
+        let mut state = expanded_with("1\n2\n3\n4\n5");
+        state.scroll_by(3, 5);
+        assert_eq!(state.scroll_offset, 3);
+        state.scroll_by(10, 5);
+        assert_eq!(state.scroll_offset, 4);
+        state.scroll_by(-2, 5);
+        assert_eq!(state.scroll_offset, 2);
+        state.scroll_by(-10, 5);
+        assert_eq!(state.scroll_offset, 0);
+    }
+
+    #[test]
+    fn test_scroll_right_stops_at_longest_visible_line() {
+        let long = "x".repeat(20);
+        let mut state = expanded_with(&format!("short\n\x1b[31m{}\x1b[0m\nshort", long));
+        state.scroll_right(8, 3);
+        state.scroll_right(8, 3);
+        assert_eq!(state.h_scroll, 16);
+        // Escape codes don't count; 24 would be past the end
+        state.scroll_right(8, 3);
+        assert_eq!(state.h_scroll, 16);
+
+        // Scrolled past the long line, only short lines are visible
+        state.scroll_left(16);
+        state.scroll_offset = 2;
+        state.scroll_right(8, 3);
+        assert_eq!(state.h_scroll, 0);
+    }
+
+    #[test]
+    fn test_panel_search_jump_wraps() {
+        let mut state = expanded_with("foo\nbar\nFOO baz\nbar");
+        state.panel_search = "foo".into();
+        state.panel_search_jump(true, true);
+        assert_eq!(state.scroll_offset, 0);
+        state.panel_search_jump(true, false);
+        assert_eq!(state.scroll_offset, 2);
+        state.panel_search_jump(true, false);
+        assert_eq!(state.scroll_offset, 0);
+        state.panel_search_jump(false, false);
+        assert_eq!(state.scroll_offset, 2);
+
+        state.panel_search = "nothing".into();
+        state.panel_search_jump(true, true);
+        assert_eq!(state.scroll_offset, 2);
+    }
+}
@@ -0,0 +1,277 @@
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::symbols::border;
+use std::str::FromStr;
+
+use super::spinner;
+use crate::config::{Settings, SETTINGS_SECTION};
+
+/// Styles and symbols the TUI draws with, from the `theme`, `icons` and
+/// `color.<role>` settings
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub ok: Style,
+    pub failed: Style,
+    pub running: Style,
+    /// Repos waiting for dependencies
+    pub blocked: Style,
+    /// Secondary text: summaries, separators, borders and hints
+    pub dim: Style,
+    /// Selection marker, focused borders and menu keys
+    pub accent: Style,
+    pub marked: Style,
+    /// Summaries that changed since the previous watch cycle
+    pub changed: Style,
+    /// Search input, messages and names matching the search
+    pub search: Style,
+    /// Search matches in output and the highlighted menu entry
+    pub highlight: Style,
+    /// Strip colors from command output as well
+    pub no_color: bool,
+    pub symbols: &'static Symbols,
+}
+
+/// Icons and line-drawing characters
+#[derive(Debug)]
+pub struct Symbols {
+    pub ok: &'static str,
+    pub failed: &'static str,
+    pub blocked: &'static str,
+    pub skipped: &'static str,
+    pub selector: &'static str,
+    pub mark: &'static str,
+    pub cursor: &'static str,
+    pub separator: &'static str,
+    pub border: border::Set<'static>,
+    pub spinner: &'static [char],
+}
+
+pub const UNICODE: Symbols = Symbols {
+    ok: "✓",
+    failed: "✗",
+    blocked: "○",
+    skipped: "-",
+    selector: "▸",
+    mark: "*",
+    cursor: "▏",
+    separator: "─",
+    border: border::PLAIN,
+    spinner: spinner::FRAMES,
+};
+
+pub const ASCII: Symbols = Symbols {
+    ok: "+",
+    failed: "x",
+    blocked: "o",
+    skipped: "-",
+    selector: ">",
+    mark: "*",
+    cursor: "_",
+    separator: "-",
+    border: border::Set {
+        top_left: "+",
+        top_right: "+",
+        bottom_left: "+",
+        bottom_right: "+",
+        vertical_left: "|",
+        vertical_right: "|",
+        horizontal_top: "-",
+        horizontal_bottom: "-",
+    },
+    spinner: spinner::ASCII_FRAMES,
+};
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            ok: Style::default().fg(Color::Green),
+            failed: Style::default().fg(Color::Red),
+            running: Style::default().fg(Color::Yellow),
+            blocked: Style::default().fg(Color::Blue),
+            dim: Style::default().fg(Color::DarkGray),
+            accent: Style::default().fg(Color::Cyan),
+            marked: Style::default().fg(Color::Magenta).bold(),
+            changed: Style::default().fg(Color::Magenta).bold(),
+            search: Style::default().fg(Color::Yellow),
+            highlight: Style::default().fg(Color::Black).bg(Color::Yellow),
+            no_color: false,
+            symbols: &UNICODE,
+        }
+    }
+}
+
+impl Theme {
+    /// Bright colors that don't rely on telling red from green
+    pub fn high_contrast() -> Self {
+        Self {
+            ok: Style::default().fg(Color::LightBlue),
+            failed: Style::default().fg(Color::LightMagenta).bold(),
+            running: Style::default().fg(Color::LightYellow),
+            blocked: Style::default().fg(Color::LightCyan),
+            dim: Style::default().fg(Color::Gray),
+            accent: Style::default().fg(Color::White).bold(),
+            marked: Style::default().fg(Color::LightCyan).bold(),
+            changed: Style::default()
+                .fg(Color::LightYellow)
+                .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            search: Style::default().fg(Color::LightYellow),
+            highlight: Style::default().fg(Color::Black).bg(Color::White),
+            ..Self::default()
+        }
+    }
+
+    /// No colors at all, only bold, underline and reverse video
+    pub fn mono() -> Self {
+        Self {
+            ok: Style::default(),
+            failed: Style::default().bold(),
+            running: Style::default(),
+            blocked: Style::default(),
+            dim: Style::default(),
+            accent: Style::default().bold(),
+            marked: Style::default().bold(),
+            changed: Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            search: Style::default().underlined(),
+            highlight: Style::default().reversed(),
+            no_color: true,
+            ..Self::default()
+        }
+    }
+
+    /// Theme from `[mrx]`. `no_color` (a non-empty `NO_COLOR` in the
+    /// environment) forces `mono`.
+    pub fn from_settings(settings: &Settings, no_color: bool) -> Self {
+        let mut theme = match settings.theme.as_deref() {
+            _ if no_color => Self::mono(),
+            None | Some("default") => Self::default(),
+            Some("high-contrast") => Self::high_contrast(),
+            Some("mono") => Self::mono(),
+            Some(other) => {
+                eprintln!(
+                    "warning: [{}] unknown theme: {} (expected default, high-contrast or mono)",
+                    SETTINGS_SECTION, other
+                );
+                Self::default()
+            }
+        };
+
+        match settings.icons.as_deref() {
+            None | Some("unicode") => {}
+            Some("ascii") => theme.symbols = &ASCII,
+            Some(other) => eprintln!(
+                "warning: [{}] unknown icons: {} (expected unicode or ascii)",
+                SETTINGS_SECTION, other
+            ),
+        }
+
+        if theme.no_color {
+            return theme;
+        }
+        for (role, value) in &settings.colors {
+            // `#` starts a comment in the config, so hex colors may omit it
+            let value = if value.len() == 6 && value.chars().all(|c| c.is_ascii_hexdigit()) {
+                format!("#{}", value)
+            } else {
+                value.clone()
+            };
+            let Ok(color) = Color::from_str(&value) else {
+                eprintln!(
+                    "warning: [{}] invalid color.{}: {}",
+                    SETTINGS_SECTION, role, value
+                );
+                continue;
+            };
+            let style = match role.as_str() {
+                "ok" => &mut theme.ok,
+                "failed" => &mut theme.failed,
+                "running" => &mut theme.running,
+                "blocked" => &mut theme.blocked,
+                "dim" => &mut theme.dim,
+                "accent" => &mut theme.accent,
+                "marked" => &mut theme.marked,
+                "changed" => &mut theme.changed,
+                "search" => &mut theme.search,
+                "highlight" => {
+                    theme.highlight = theme.highlight.bg(color);
+                    continue;
+                }
+                _ => {
+                    eprintln!(
+                        "warning: [{}] unknown color role: {}",
+                        SETTINGS_SECTION, role
+                    );
+                    continue;
+                }
+            };
+            *style = style.fg(color);
+        }
+        theme
+    }
+
+    /// Style for text from command output: its own colors are dropped in
+    /// monochrome mode
+    pub fn output(&self, mut style: Style) -> Style {
+        if self.no_color {
+            style.fg = None;
+            style.bg = None;
+        }
+        style
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(theme: Option<&str>, colors: &[(&str, &str)]) -> Settings {
+        Settings {
+            theme: theme.map(String::from),
+            colors: colors
+                .iter()
+                .map(|(r, c)| (r.to_string(), c.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_no_color_forces_mono() {
+        let theme = Theme::from_settings(&settings(Some("high-contrast"), &[("ok", "red")]), true);
+        assert!(theme.no_color);
+        assert_eq!(theme.ok, Style::default());
+        assert_eq!(
+            theme.output(Style::default().fg(Color::Red)),
+            Style::default()
+        );
+    }
+
+    #[test]
+    fn test_unknown_theme_falls_back_to_default() {
+        let theme = Theme::from_settings(&settings(Some("solarized"), &[]), false);
+        assert_eq!(theme.ok, Theme::default().ok);
+        assert!(!theme.no_color);
+    }
+
+    #[test]
+    fn test_color_overrides() {
+        let theme = Theme::from_settings(
+            &settings(
+                None,
+                &[
+                    ("ok", "ff8800"),
+                    ("failed", "208"),
+                    ("accent", "notacolor"),
+                    ("highlight", "blue"),
+                ],
+            ),
+            false,
+        );
+        assert_eq!(theme.ok, Style::default().fg(Color::Rgb(0xff, 0x88, 0x00)));
+        assert_eq!(theme.failed, Style::default().fg(Color::Indexed(208)));
+        assert_eq!(theme.accent, Theme::default().accent);
+        // Only the background changes, so matches stay readable
+        assert_eq!(
+            theme.highlight,
+            Style::default().fg(Color::Black).bg(Color::Blue)
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use crate::config::CloneSpec;
+use std::path::{Path, PathBuf};
+
+/// Version control system managing a repo directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Vcs {
+    #[default]
+    Git,
+    Hg,
+    Jj,
+    /// A plain directory: only `run` does anything
+    Plain,
+}
+
+impl Vcs {
+    /// Parse the value of a section's `vcs =` key
+    pub fn parse(value: &str) -> Option<Vcs> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "git" => Some(Vcs::Git),
+            "hg" | "mercurial" => Some(Vcs::Hg),
+            "jj" | "jujutsu" => Some(Vcs::Jj),
+            "plain" | "none" => Some(Vcs::Plain),
+            _ => None,
+        }
+    }
+
+    /// Detect the VCS of an existing directory. Jujutsu is checked first
+    /// because colocated jj repos also have a `.git`.
+    pub fn detect(path: &Path) -> Option<Vcs> {
+        if path.join(".jj").is_dir() {
+            Some(Vcs::Jj)
+        } else if path.join(".git").exists() {
+            Some(Vcs::Git)
+        } else if path.join(".hg").is_dir() {
+            Some(Vcs::Hg)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Vcs::Git => "git",
+            Vcs::Hg => "hg",
+            Vcs::Jj => "jj",
+            Vcs::Plain => "plain",
+        }
+    }
+
+    /// Executable to run for this VCS
+    pub fn program(self) -> &'static str {
+        match self {
+            Vcs::Git | Vcs::Plain => "git",
+            Vcs::Hg => "hg",
+            Vcs::Jj => "jj",
+        }
+    }
+
+    /// Arguments for cloning `spec` into `dest`
+    pub fn clone_args(self, spec: &CloneSpec, dest: &Path) -> Vec<String> {
+        let dest = dest.to_string_lossy().to_string();
+        match self {
+            Vcs::Git | Vcs::Plain => spec.args(Path::new(&dest)),
+            Vcs::Hg => {
+                let mut args = vec!["clone".to_string()];
+                args.extend(spec.extra_args.iter().cloned());
+                if let Some(branch) = &spec.branch {
+                    args.push("--branch".into());
+                    args.push(branch.clone());
+                }
+                args.push("--".into());
+                args.push(spec.url.clone());
+                args.push(dest);
+                args
+            }
+            Vcs::Jj => {
+                let mut args = vec!["git".to_string(), "clone".to_string()];
+                args.extend(spec.extra_args.iter().cloned());
+                if let Some(branch) = &spec.branch {
+                    args.push("--branch".into());
+                    args.push(branch.clone());
+                }
+                if let Some(depth) = spec.depth {
+                    args.push(format!("--depth={}", depth));
+                }
+                args.push(spec.url.clone());
+                args.push(dest);
+                args
+            }
+        }
+    }
+}
+
+/// Git dir of a checkout. In linked worktrees and submodules `.git` is a
+/// file pointing at it (`gitdir: <path>`).
+pub fn git_dir(repo_path: &Path) -> Option<PathBuf> {
+    let dot_git = repo_path.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+    let content = std::fs::read_to_string(&dot_git).ok()?;
+    let dir = content.lines().next()?.strip_prefix("gitdir:")?.trim();
+    Some(repo_path.join(dir))
+}
+
+/// Directory with the state a repo's worktrees share (objects, refs,
+/// `shallow`): the git dir, or the one its `commondir` file names.
+pub fn git_common_dir(repo_path: &Path) -> Option<PathBuf> {
+    let git_dir = git_dir(repo_path)?;
+    match std::fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common) => Some(git_dir.join(common.trim())),
+        Err(_) => Some(git_dir),
+    }
+}
+
+/// Whether a git checkout has truncated history
+pub fn is_shallow(repo_path: &Path) -> bool {
+    git_common_dir(repo_path).is_some_and(|dir| dir.join("shallow").exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{git, init_repo, TempDir};
+
+    #[test]
+    fn test_is_shallow_in_worktrees_and_submodule_layouts() {
+        let tmp = TempDir::new("shallow");
+        let origin = tmp.path().join("origin");
+        init_repo(&origin);
+        git(&origin, &["commit", "-q", "--allow-empty", "-m", "second"]);
+        assert!(!is_shallow(&origin));
+
+        let url = format!("file://{}", origin.display());
+        git(tmp.path(), &["clone", "-q", "--depth=1", &url, "clone"]);
+        let clone = tmp.path().join("clone");
+        assert!(is_shallow(&clone));
+
+        git(&clone, &["worktree", "add", "-q", "../wt", "-b", "wt"]);
+        assert!(is_shallow(&tmp.path().join("wt")));
+
+        // Submodules keep their git dir under the superproject's .git/modules
+        let sub = tmp.path().join("super/sub");
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::create_dir_all(tmp.path().join("super/.git/modules/sub")).unwrap();
+        std::fs::write(tmp.path().join("super/.git/modules/sub/shallow"), "").unwrap();
+        std::fs::write(sub.join(".git"), "gitdir: ../.git/modules/sub\n").unwrap();
+        assert!(is_shallow(&sub));
+    }
+}
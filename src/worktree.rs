@@ -0,0 +1,254 @@
+use crate::config::Repo;
+use crate::register::absolute_path;
+use crate::shellwords;
+use crate::vcs;
+use std::path::{Path, PathBuf};
+use std::process::Command as StdCommand;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Worktree {
+    pub path: PathBuf,
+    /// Checked-out branch, `None` if HEAD is detached
+    pub branch: Option<String>,
+    pub bare: bool,
+}
+
+impl Worktree {
+    pub fn label(&self) -> String {
+        match &self.branch {
+            Some(b) => b.clone(),
+            None if self.bare => "(bare)".into(),
+            None => "(detached)".into(),
+        }
+    }
+}
+
+/// Worktrees of a repo, main worktree first. Empty if git can't list them.
+pub fn list(repo_path: &Path) -> Vec<Worktree> {
+    let output = StdCommand::new("git")
+        .args(["worktree", "list", "--porcelain"])
+        .current_dir(repo_path)
+        .output();
+    match output {
+        Ok(o) if o.status.success() => parse_porcelain(&String::from_utf8_lossy(&o.stdout)),
+        _ => Vec::new(),
+    }
+}
+
+fn parse_porcelain(output: &str) -> Vec<Worktree> {
+    let mut worktrees = Vec::new();
+    let mut current: Option<Worktree> = None;
+
+    for line in output.lines() {
+        if let Some(path) = line.strip_prefix("worktree ") {
+            worktrees.extend(current.take());
+            current = Some(Worktree {
+                path: PathBuf::from(path),
+                branch: None,
+                bare: false,
+            });
+        } else if let Some(wt) = current.as_mut() {
+            if let Some(branch) = line.strip_prefix("branch ") {
+                let branch = branch.strip_prefix("refs/heads/").unwrap_or(branch);
+                wt.branch = Some(branch.to_string());
+            } else if line == "bare" {
+                wt.bare = true;
+            }
+        }
+    }
+    worktrees.extend(current);
+    worktrees
+}
+
+fn is_dirty(path: &Path) -> Option<bool> {
+    let output = StdCommand::new("git")
+        .args(["status", "--porcelain"])
+        .current_dir(path)
+        .output()
+        .ok()?;
+    output.status.success().then_some(!output.stdout.is_empty())
+}
+
+/// Print every repo's worktrees with their branch and dirty state
+pub async fn print_all(repos: &[Repo]) {
+    let handles: Vec<_> = repos
+        .iter()
+        .filter(|r| r.path.is_dir())
+        .map(|r| {
+            let repo = r.clone();
+            tokio::task::spawn_blocking(move || {
+                let worktrees: Vec<(Worktree, Option<bool>)> = list(&repo.path)
+                    .into_iter()
+                    .map(|wt| {
+                        let dirty = if wt.bare { None } else { is_dirty(&wt.path) };
+                        (wt, dirty)
+                    })
+                    .collect();
+                (repo, worktrees)
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        let Ok((repo, worktrees)) = handle.await else {
+            continue;
+        };
+        println!("{}", repo.name);
+        let width = worktrees
+            .iter()
+            .map(|(wt, _)| wt.label().len())
+            .max()
+            .unwrap_or(0);
+        for (wt, dirty) in &worktrees {
+            let state = match dirty {
+                Some(true) => "dirty",
+                Some(false) => "clean",
+                None => "-",
+            };
+            println!(
+                "  {:width$}  {:5}  {}",
+                wt.label(),
+                state,
+                wt.path.display(),
+                width = width
+            );
+        }
+    }
+}
+
+/// Add a row for every linked worktree after its repo's own row
+pub fn expand(repos: Vec<Repo>) -> Vec<Repo> {
+    let mut rows = Vec::new();
+    for repo in repos {
+        let linked: Vec<Worktree> = if repo.path.is_dir() {
+            let main_path = absolute_path(&repo.path);
+            list(&repo.path)
+                .into_iter()
+                .filter(|wt| absolute_path(&wt.path) != main_path && !wt.bare)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let name = repo.name.clone();
+        let section = repo.section.clone();
+        rows.push(repo.clone());
+        for wt in linked {
+            rows.push(Repo {
+                name: format!("{}@{}", name, wt.label()),
+                section: section.clone(),
+                path: wt.path,
+                ..repo.clone()
+            });
+        }
+    }
+    rows
+}
+
+/// Where `worktree add` puts a repo's worktree for `branch`: the repo's
+/// section path under `<root>/<branch>`, with `root` defaulting to
+/// `.worktrees` in the base dir. Slashes in the branch become dashes, so
+/// `feature/x` and `feature-x` share a directory; `plan` only skips an
+/// existing one when it has the same branch checked out.
+pub fn worktree_path(repo: &Repo, root: Option<&Path>, branch: &str) -> PathBuf {
+    let root = match root {
+        Some(r) => r.to_path_buf(),
+        None => {
+            let depth = Path::new(&repo.section).components().count();
+            let base_dir = repo.path.ancestors().nth(depth).unwrap_or(&repo.path);
+            base_dir.join(".worktrees")
+        }
+    };
+    root.join(branch.replace('/', "-")).join(&repo.section)
+}
+
+/// Branch checked out in an existing worktree, read from its `HEAD` file.
+/// `None` if the directory isn't a checkout or HEAD is detached.
+pub fn checked_out_branch(path: &Path) -> Option<String> {
+    let head = std::fs::read_to_string(vcs::git_dir(path)?.join("HEAD")).ok()?;
+    head.trim()
+        .strip_prefix("ref: refs/heads/")
+        .map(|b| b.to_string())
+}
+
+/// Shell command for `git worktree add`, creating the branch if neither a
+/// local branch nor an `origin` branch of that name exists. The branch is
+/// looked up when the operation runs, so planning doesn't wait on git.
+pub fn add_command(branch: &str, dest: &Path) -> String {
+    let exists = |prefix: &str| {
+        format!(
+            "git show-ref --verify --quiet {}",
+            shellwords::quote(&format!("{}{}", prefix, branch))
+        )
+    };
+    let branch_q = shellwords::quote(branch);
+    let dest = shellwords::quote(&dest.to_string_lossy());
+    format!(
+        "if {} || {}; then git worktree add {} {}; else git worktree add -b {} {}; fi",
+        exists("refs/heads/"),
+        exists("refs/remotes/origin/"),
+        dest,
+        branch_q,
+        branch_q,
+        dest
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_porcelain() {
+        let output = "\
+worktree /src/api
+HEAD 1111111111111111111111111111111111111111
+branch refs/heads/main
+
+worktree /src/.worktrees/feature-x/api
+HEAD 2222222222222222222222222222222222222222
+branch refs/heads/feature/x
+locked
+
+worktree /tmp/review
+HEAD 3333333333333333333333333333333333333333
+detached
+";
+        let wts = parse_porcelain(output);
+        assert_eq!(wts.len(), 3);
+        assert_eq!(wts[0].label(), "main");
+        assert_eq!(wts[1].branch.as_deref(), Some("feature/x"));
+        assert_eq!(wts[1].path, PathBuf::from("/src/.worktrees/feature-x/api"));
+        assert_eq!(wts[2].label(), "(detached)");
+    }
+
+    #[test]
+    fn test_add_command_and_branch_collision() {
+        use crate::testutil::{git, init_repo, TempDir};
+
+        let tmp = TempDir::new("worktree");
+        let repo = tmp.path().join("api");
+        init_repo(&repo);
+        let run = |branch: &str, dest: &Path| {
+            std::process::Command::new("sh")
+                .args(["-c", &add_command(branch, dest)])
+                .current_dir(&repo)
+                .output()
+                .unwrap()
+        };
+
+        // New branch is created, an existing one is checked out
+        let dest = tmp.path().join("wt/feature-x");
+        assert!(run("feature/x", &dest).status.success());
+        assert_eq!(checked_out_branch(&dest).as_deref(), Some("feature/x"));
+        git(&repo, &["branch", "existing"]);
+        let existing = tmp.path().join("wt/existing");
+        assert!(run("existing", &existing).status.success());
+        assert_eq!(checked_out_branch(&existing).as_deref(), Some("existing"));
+        assert_eq!(checked_out_branch(&repo).as_deref(), Some("main"));
+
+        // A different branch mapping to the same directory fails
+        let output = run("feature-x", &dest);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("already exists"));
+    }
+}